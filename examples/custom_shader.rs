@@ -0,0 +1,119 @@
+//! Renders the crab with a custom toon shader written against the `VertexShader` and
+//! `FragmentShader` traits, and writes the result to a PNG.
+//!
+//! Run with `cargo run --release --example custom_shader [output.png]`
+
+use std::path::Path;
+
+use software_renderer::texture::Tex2;
+use software_renderer::vector::Vec4;
+use software_renderer::{
+    prepare_shaded_triangles, render_shaded, Camera, Color, ColorBuffer, DepthBuffer, Fragment,
+    FragmentShader, Mat4, Material, Mesh, RenderSettings, Texture, Vec3, VertexInput, VertexOutput,
+    VertexShader,
+};
+
+const RENDER_WIDTH: u16 = 800;
+const RENDER_HEIGHT: u16 = 600;
+
+/// Lights the texture with a few flat bands of brightness instead of a smooth falloff
+struct ToonShader {
+    world_matrix: Mat4,
+    normal_matrix: Mat4,
+    view_projection_matrix: Mat4,
+    direction_to_light: Vec3,
+    texture: Texture,
+}
+
+impl VertexShader for ToonShader {
+    /// Texture coordinates and the world space normal
+    type Varyings = (Tex2, Vec3);
+
+    fn shade_vertex(&self, vertex: &VertexInput) -> VertexOutput<Self::Varyings> {
+        let world_position = self.world_matrix * Vec4::from(vertex.position);
+        let normal =
+            self.normal_matrix * Vec4::new(vertex.normal.x, vertex.normal.y, vertex.normal.z, 0.0);
+
+        VertexOutput {
+            position: self.view_projection_matrix * world_position,
+            varyings: (vertex.uv, Vec3::from(normal)),
+        }
+    }
+}
+
+impl FragmentShader<(Tex2, Vec3)> for ToonShader {
+    fn shade_fragment(&self, fragment: &Fragment<(Tex2, Vec3)>) -> Option<Color> {
+        let (uv, normal) = fragment.varyings;
+
+        let intensity = normal.normalized_or_zero().dot(self.direction_to_light);
+        let band = match intensity {
+            intensity if intensity > 0.8 => 1.0,
+            intensity if intensity > 0.4 => 0.7,
+            intensity if intensity > 0.0 => 0.45,
+            _ => 0.25,
+        };
+
+        Some(self.texture.sample(Tex2::new(uv.u, 1.0 - uv.v)) * band)
+    }
+}
+
+fn main() {
+    let output_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "custom_shader.png".to_string());
+
+    let mesh = Mesh::from_obj(
+        Path::new("assets/crab.obj"),
+        Vec3::new(0.0, 2.5, 0.0),
+        Vec3::splat(1.0),
+        Vec3::default(),
+    )
+    .unwrap_or_else(|err| panic!("{err}"));
+    let texture = Texture::from_png(Path::new("assets/crab.png"))
+        .expect("example must be run from the repository root");
+
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, -3.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        0.0,
+    );
+    let projection_matrix = Mat4::projection(
+        std::f32::consts::FRAC_PI_2,
+        RENDER_HEIGHT as f32 / RENDER_WIDTH as f32,
+        0.1,
+        100.0,
+    );
+
+    let shader = ToonShader {
+        world_matrix: mesh.world_matrix(),
+        normal_matrix: mesh.normal_matrix(),
+        view_projection_matrix: projection_matrix * camera.view_matrix(),
+        direction_to_light: Vec3::new(-1.0, 1.0, -1.0).normalized(),
+        texture,
+    };
+
+    let settings = RenderSettings::new(RENDER_WIDTH, RENDER_HEIGHT);
+    let mut color_buffer = ColorBuffer::new(RENDER_WIDTH, RENDER_HEIGHT);
+    let mut depth_buffer = DepthBuffer::new(RENDER_WIDTH, RENDER_HEIGHT);
+    color_buffer.clear(Color::new(0x20, 0x20, 0x28));
+    depth_buffer.clear(1.0);
+
+    let mut triangles_to_render = Vec::new();
+    prepare_shaded_triangles(&mut triangles_to_render, &mesh, &shader, &settings);
+    render_shaded(
+        &mut color_buffer,
+        &mut depth_buffer,
+        &triangles_to_render,
+        &shader,
+        &settings,
+        &Material::default(),
+    );
+
+    if let Err(error) = color_buffer.write_png(Path::new(&output_path)) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+
+    println!("Wrote {output_path}");
+}
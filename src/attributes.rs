@@ -0,0 +1,125 @@
+use std::ops::{Add, Div, Index, IndexMut, Mul, Sub};
+
+use crate::color::Color;
+use crate::shader::Varyings;
+
+/// A fixed number of values carried by each vertex, interpolated when clipping and across
+/// the pixels of a triangle like any other vertex data. Shaders can use them as varyings
+/// for whatever they like, while the built-in pipeline packs its lighting data and vertex
+/// colors into them as laid out by `VertexData`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attributes<const N: usize>(pub [f32; N]);
+
+impl<const N: usize> Attributes<N> {
+    pub const fn new(values: [f32; N]) -> Self {
+        Self(values)
+    }
+
+    pub const fn splat(value: f32) -> Self {
+        Self([value; N])
+    }
+}
+
+impl<const N: usize> Default for Attributes<N> {
+    fn default() -> Self {
+        Self::splat(0.0)
+    }
+}
+
+impl<const N: usize> Index<usize> for Attributes<N> {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl<const N: usize> IndexMut<usize> for Attributes<N> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.0[index]
+    }
+}
+
+impl<const N: usize> Add for Attributes<N> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(std::array::from_fn(|i| self.0[i] + rhs.0[i]))
+    }
+}
+
+impl<const N: usize> Sub for Attributes<N> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(std::array::from_fn(|i| self.0[i] - rhs.0[i]))
+    }
+}
+
+impl<const N: usize> Mul<f32> for Attributes<N> {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self(self.0.map(|value| value * rhs))
+    }
+}
+
+impl<const N: usize> Mul<Attributes<N>> for f32 {
+    type Output = Attributes<N>;
+
+    fn mul(self, rhs: Attributes<N>) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl<const N: usize> Div<f32> for Attributes<N> {
+    type Output = Self;
+
+    fn div(self, rhs: f32) -> Self::Output {
+        Self(self.0.map(|value| value / rhs))
+    }
+}
+
+impl<const N: usize> Varyings for Attributes<N> {
+    fn weighted_sum<const M: usize>(values: [Self; M], weights: [f32; M]) -> Self {
+        values
+            .into_iter()
+            .zip(weights)
+            .fold(Self::default(), |sum, (value, weight)| sum + value * weight)
+    }
+}
+
+/// Red, green, blue and alpha from 0 to 1
+impl From<Color> for Attributes<4> {
+    fn from(color: Color) -> Self {
+        Self([color.r, color.g, color.b, color.a].map(|channel| channel as f32 / 255.0))
+    }
+}
+
+/// Rounds each channel, so interpolating a color between vertices of the same color gives
+/// that color back exactly
+impl From<Attributes<4>> for Color {
+    fn from(attributes: Attributes<4>) -> Self {
+        let [r, g, b, a] = attributes
+            .0
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+        Self::rgba(r, g, b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_survive_interpolation() {
+        let color = Color::rgba(10, 200, 255, 128);
+        let attributes = Attributes::from(color);
+
+        let interpolated =
+            Attributes::weighted_sum([attributes; 3], [0.3, 0.45, 0.25]) / (0.3 + 0.45 + 0.25);
+
+        assert_eq!(Color::from(interpolated), color);
+    }
+}
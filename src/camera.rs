@@ -1,5 +1,5 @@
 use crate::matrix::Mat4;
-use crate::vector::{Vec3, Vec4};
+use crate::vector::Vec3;
 
 #[derive(Debug)]
 pub struct Camera {
@@ -27,20 +27,6 @@ impl Camera {
     }
 
     pub fn view_matrix(&self) -> Mat4 {
-        let z = self.forward();
-        let x = self.up.cross(z).normalized();
-        let y = z.cross(x).normalized();
-
-        Mat4::new(
-            Vec4::new(x.x, y.x, z.x, 0.0),
-            Vec4::new(x.y, y.y, z.y, 0.0),
-            Vec4::new(x.z, y.z, z.z, 0.0),
-            Vec4::new(
-                -x.dot(self.translation),
-                -y.dot(self.translation),
-                -z.dot(self.translation),
-                1.0,
-            ),
-        )
+        Mat4::look_to(self.translation, self.forward(), self.up)
     }
 }
@@ -1,36 +1,88 @@
-use std::ops::Mul;
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    /// Opacity, from 0 (fully transparent) to 255 (fully opaque)
+    pub a: u8,
 }
 
 impl Color {
+    /// Creates a fully opaque color
     pub fn new(r: u8, g: u8, b: u8) -> Self {
-        Self { r, g, b }
+        Self { r, g, b, a: 0xFF }
+    }
+
+    pub fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Opacity as a fraction, where 1.0 is fully opaque
+    pub fn alpha(&self) -> f32 {
+        self.a as f32 / 255.0
+    }
+
+    /// Linearly interpolates every channel, including alpha, between two colors
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        let lerp_channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+
+        Self::rgba(
+            lerp_channel(self.r, other.r),
+            lerp_channel(self.g, other.g),
+            lerp_channel(self.b, other.b),
+            lerp_channel(self.a, other.a),
+        )
     }
 }
 
+/// Packs a color as 0x00RRGGBB, the format pixels are displayed in. Alpha is dropped.
 impl From<Color> for u32 {
     fn from(color: Color) -> Self {
         (color.r as u32) << 16 | (color.g as u32) << 8 | (color.b as u32)
     }
 }
 
+/// Unpacks a 0x00RRGGBB pixel as an opaque color
+impl From<u32> for Color {
+    fn from(color: u32) -> Self {
+        Self::new((color >> 16) as u8, (color >> 8) as u8, color as u8)
+    }
+}
+
+/// Adds the color channels, keeping the left hand side's alpha
+impl Add<Color> for Color {
+    type Output = Color;
+
+    fn add(self, rhs: Color) -> Self::Output {
+        Self::rgba(
+            self.r.saturating_add(rhs.r),
+            self.g.saturating_add(rhs.g),
+            self.b.saturating_add(rhs.b),
+            self.a,
+        )
+    }
+}
+
+/// Scales the color channels, keeping alpha
 impl Mul<f32> for Color {
     type Output = Color;
 
     fn mul(self, rhs: f32) -> Self::Output {
-        Self::new(
+        Self::rgba(
             (self.r as f32 * rhs) as u8,
             (self.g as f32 * rhs) as u8,
             (self.b as f32 * rhs) as u8,
+            self.a,
         )
     }
 }
 
+/// Modulates every channel, including alpha
 impl Mul<Color> for Color {
     type Output = Color;
 
@@ -38,7 +90,319 @@ impl Mul<Color> for Color {
         let r = (self.r as f32 / 255.0) * (rhs.r as f32 / 255.0);
         let g = (self.g as f32 / 255.0) * (rhs.g as f32 / 255.0);
         let b = (self.b as f32 / 255.0) * (rhs.b as f32 / 255.0);
+        let a = (self.a as f32 / 255.0) * (rhs.a as f32 / 255.0);
+
+        Self::rgba(
+            (r * 255.0) as u8,
+            (g * 255.0) as u8,
+            (b * 255.0) as u8,
+            (a * 255.0) as u8,
+        )
+    }
+}
+
+/// Number of entries in the table used to encode linear values as sRGB. Dark values are
+/// stretched out the most by the encoding, so the table needs to be much finer than 8 bits.
+const SRGB_ENCODE_TABLE_SIZE: usize = 1 << 14;
+
+/// Converts an sRGB encoded channel, from 0 to 1, into linear light
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a channel of linear light, from 0 to 1, into its sRGB encoding
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear light of each 8-bit sRGB value
+fn srgb_decode_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+
+    TABLE.get_or_init(|| std::array::from_fn(|value| srgb_to_linear(value as f32 / 255.0)))
+}
+
+/// 8-bit sRGB value of evenly spaced amounts of linear light
+fn srgb_encode_table() -> &'static [u8] {
+    static TABLE: OnceLock<Vec<u8>> = OnceLock::new();
+
+    TABLE.get_or_init(|| {
+        (0..SRGB_ENCODE_TABLE_SIZE)
+            .map(|index| {
+                let value = index as f32 / (SRGB_ENCODE_TABLE_SIZE - 1) as f32;
+                (linear_to_srgb(value) * 255.0).round() as u8
+            })
+            .collect()
+    })
+}
+
+/// Color with linear floating point channels and an alpha, used to light and blend pixels in
+/// linear space. Channels can exceed 1.0 until the color is encoded as sRGB.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinearColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    /// Opacity, from 0 (fully transparent) to 1 (fully opaque)
+    pub a: f32,
+}
+
+impl LinearColor {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Decodes an 8-bit sRGB color. Alpha isn't encoded, so it's only scaled.
+    pub fn from_srgb(color: Color) -> Self {
+        let table = srgb_decode_table();
+
+        Self::new(
+            table[color.r as usize],
+            table[color.g as usize],
+            table[color.b as usize],
+            color.alpha(),
+        )
+    }
+
+    /// Encodes the color as 8-bit sRGB, saturating channels brighter than full intensity
+    pub fn to_srgb(self) -> Color {
+        let table = srgb_encode_table();
+        let encode = |value: f32| {
+            let index = value.clamp(0.0, 1.0) * (SRGB_ENCODE_TABLE_SIZE - 1) as f32 + 0.5;
+            table[index as usize]
+        };
+
+        Color::rgba(
+            encode(self.r),
+            encode(self.g),
+            encode(self.b),
+            (self.a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        )
+    }
+
+    /// The color channels without alpha
+    pub fn rgb(self) -> FloatColor {
+        FloatColor::new(self.r, self.g, self.b)
+    }
+
+    /// Linearly interpolates every channel, including alpha, between two colors
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+/// Adds every channel, including alpha
+impl Add<LinearColor> for LinearColor {
+    type Output = LinearColor;
+
+    fn add(self, rhs: LinearColor) -> Self::Output {
+        Self::new(
+            self.r + rhs.r,
+            self.g + rhs.g,
+            self.b + rhs.b,
+            self.a + rhs.a,
+        )
+    }
+}
+
+impl Sub<LinearColor> for LinearColor {
+    type Output = LinearColor;
+
+    fn sub(self, rhs: LinearColor) -> Self::Output {
+        Self::new(
+            self.r - rhs.r,
+            self.g - rhs.g,
+            self.b - rhs.b,
+            self.a - rhs.a,
+        )
+    }
+}
+
+/// Scales every channel, including alpha
+impl Mul<f32> for LinearColor {
+    type Output = LinearColor;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self::new(self.r * rhs, self.g * rhs, self.b * rhs, self.a * rhs)
+    }
+}
+
+/// Modulates every channel, including alpha
+impl Mul<LinearColor> for LinearColor {
+    type Output = LinearColor;
+
+    fn mul(self, rhs: LinearColor) -> Self::Output {
+        Self::new(
+            self.r * rhs.r,
+            self.g * rhs.g,
+            self.b * rhs.b,
+            self.a * rhs.a,
+        )
+    }
+}
+
+/// Adds light to the color channels, keeping alpha
+impl Add<FloatColor> for LinearColor {
+    type Output = LinearColor;
+
+    fn add(self, rhs: FloatColor) -> Self::Output {
+        Self::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b, self.a)
+    }
+}
+
+/// Light a color, keeping alpha
+impl Mul<FloatColor> for LinearColor {
+    type Output = LinearColor;
+
+    fn mul(self, rhs: FloatColor) -> Self::Output {
+        Self::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b, self.a)
+    }
+}
+
+/// Color with floating point channels, where 1.0 is full intensity. Used for lighting,
+/// where values can exceed 1.0 before being applied to a surface color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub struct FloatColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl FloatColor {
+    pub const BLACK: Self = Self::splat(0.0);
+    pub const WHITE: Self = Self::splat(1.0);
+
+    pub const fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    pub const fn splat(val: f32) -> Self {
+        Self {
+            r: val,
+            g: val,
+            b: val,
+        }
+    }
+}
+
+impl From<Color> for FloatColor {
+    fn from(color: Color) -> Self {
+        Self::new(
+            color.r as f32 / 255.0,
+            color.g as f32 / 255.0,
+            color.b as f32 / 255.0,
+        )
+    }
+}
+
+impl From<FloatColor> for Color {
+    fn from(color: FloatColor) -> Self {
+        Self::new(
+            (color.r * 255.0) as u8,
+            (color.g * 255.0) as u8,
+            (color.b * 255.0) as u8,
+        )
+    }
+}
+
+impl Add<FloatColor> for FloatColor {
+    type Output = FloatColor;
+
+    fn add(self, rhs: FloatColor) -> Self::Output {
+        Self::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl AddAssign<FloatColor> for FloatColor {
+    fn add_assign(&mut self, rhs: FloatColor) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<FloatColor> for FloatColor {
+    type Output = FloatColor;
+
+    fn sub(self, rhs: FloatColor) -> Self::Output {
+        Self::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b)
+    }
+}
+
+impl Mul<f32> for FloatColor {
+    type Output = FloatColor;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl Mul<FloatColor> for f32 {
+    type Output = FloatColor;
+
+    fn mul(self, rhs: FloatColor) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl Mul<FloatColor> for FloatColor {
+    type Output = FloatColor;
+
+    fn mul(self, rhs: FloatColor) -> Self::Output {
+        Self::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
+}
+
+impl Div<f32> for FloatColor {
+    type Output = FloatColor;
+
+    fn div(self, rhs: f32) -> Self::Output {
+        Self::new(self.r / rhs, self.g / rhs, self.b / rhs)
+    }
+}
+
+/// Light a color, saturating channels that end up brighter than full intensity
+impl Mul<FloatColor> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: FloatColor) -> Self::Output {
+        Self::rgba(
+            (self.r as f32 * rhs.r) as u8,
+            (self.g as f32 * rhs.g) as u8,
+            (self.b as f32 * rhs.b) as u8,
+            self.a,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_values_survive_decoding_and_encoding() {
+        for value in 0..=255 {
+            let color = Color::rgba(value, value, value, value);
+
+            assert_eq!(LinearColor::from_srgb(color).to_srgb(), color);
+        }
+    }
+
+    #[test]
+    fn mid_gray_is_darker_in_linear_space() {
+        let gray = LinearColor::from_srgb(Color::new(128, 128, 128));
 
-        Self::new((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+        assert!((gray.r - 0.2159).abs() < 0.001);
+        // Half of full intensity is encoded much brighter than half of 255
+        assert_eq!(
+            LinearColor::new(0.5, 0.5, 0.5, 1.0).to_srgb(),
+            Color::new(188, 188, 188)
+        );
     }
 }
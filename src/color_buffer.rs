@@ -1,20 +1,115 @@
-use crate::color::Color;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::color::{Color, FloatColor, LinearColor};
+use crate::hdr::HdrSettings;
+use crate::material::BlendMode;
+use crate::tile::ScreenRect;
+
+#[derive(Debug, Clone)]
+pub enum PngWriteError<'a> {
+    WriteError(Cow<'a, str>),
+    EncodeError,
+}
+
+impl std::fmt::Display for PngWriteError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WriteError(path) => {
+                write!(f, "could not open PNG file for writing at {path}")
+            }
+            Self::EncodeError => {
+                write!(f, "could not encode PNG image")
+            }
+        }
+    }
+}
 
 pub struct ColorBuffer {
+    /// Pixels packed as 0x00RRGGBB, the format the viewer's window displays
     buffer: Vec<u32>,
+    /// Linear light of each pixel, which can be brighter than full intensity. Buffers with
+    /// one keep it in step with the packed pixels, which hold the same colors clamped and
+    /// encoded as sRGB until the buffer is resolved.
+    hdr: Option<Vec<FloatColor>>,
     width: u16,
     height: u16,
+    /// Screen position of the buffer's top left pixel, which is only offset from the corner
+    /// of the screen for buffers holding a single tile
+    x: u16,
+    y: u16,
 }
 
 impl ColorBuffer {
     pub fn new(width: u16, height: u16) -> Self {
         Self {
             buffer: vec![0; width as usize * height as usize],
+            hdr: None,
             width,
             height,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    /// Creates a buffer that also stores each pixel in floating point, so pixels lit brighter
+    /// than full intensity keep their brightness until they're tone mapped by `resolve_hdr`
+    pub fn new_hdr(width: u16, height: u16) -> Self {
+        Self {
+            hdr: Some(vec![FloatColor::BLACK; width as usize * height as usize]),
+            ..Self::new(width, height)
         }
     }
 
+    /// Copies the part of the buffer covered by a tile into a buffer of its own
+    pub(crate) fn copy_tile(&self, rect: ScreenRect) -> Self {
+        let mut buffer = Vec::with_capacity(rect.width as usize * rect.height as usize);
+        let mut hdr = self
+            .hdr
+            .as_ref()
+            .map(|_| Vec::with_capacity(rect.width as usize * rect.height as usize));
+
+        for row in rect.y..rect.y + rect.height {
+            let start = self.width as usize * row as usize + rect.x as usize;
+            let end = start + rect.width as usize;
+
+            buffer.extend_from_slice(&self.buffer[start..end]);
+            if let (Some(tile_hdr), Some(self_hdr)) = (&mut hdr, &self.hdr) {
+                tile_hdr.extend_from_slice(&self_hdr[start..end]);
+            }
+        }
+
+        Self {
+            buffer,
+            hdr,
+            width: rect.width,
+            height: rect.height,
+            x: rect.x,
+            y: rect.y,
+        }
+    }
+
+    /// Copies a tile's pixels back into the part of the buffer it covers
+    pub(crate) fn write_tile(&mut self, rect: ScreenRect, tile: &ColorBuffer) {
+        for (row, pixels) in tile.buffer.chunks_exact(tile.width as usize).enumerate() {
+            let start = self.width as usize * (rect.y as usize + row) + rect.x as usize;
+            self.buffer[start..start + pixels.len()].copy_from_slice(pixels);
+
+            if let (Some(hdr), Some(tile_hdr)) = (&mut self.hdr, &tile.hdr) {
+                let tile_start = row * tile.width as usize;
+                hdr[start..start + pixels.len()]
+                    .copy_from_slice(&tile_hdr[tile_start..tile_start + pixels.len()]);
+            }
+        }
+    }
+
+    /// Region of the screen the buffer covers
+    pub(crate) fn screen_rect(&self) -> ScreenRect {
+        ScreenRect::new(self.x, self.y, self.width, self.height)
+    }
+
     pub fn width(&self) -> u16 {
         self.width
     }
@@ -29,16 +124,211 @@ impl ColorBuffer {
 
     pub fn clear(&mut self, color: Color) {
         self.buffer.fill(color.into());
+
+        if let Some(hdr) = &mut self.hdr {
+            hdr.fill(LinearColor::from_srgb(color).rgb());
+        }
     }
 
     pub fn set(&mut self, x: u16, y: u16, color: Color) {
         assert!(x < self.width);
         assert!(y < self.height);
 
-        self.buffer[self.width as usize * y as usize + x as usize] = color.into();
+        self.set_index(self.width as usize * y as usize + x as usize, color);
     }
 
     pub fn set_index(&mut self, i: usize, color: Color) {
         self.buffer[i] = color.into();
+
+        if let Some(hdr) = &mut self.hdr {
+            hdr[i] = LinearColor::from_srgb(color).rgb();
+        }
+    }
+
+    /// Sets a pixel to a color in linear light, keeping channels brighter than full intensity
+    /// if the buffer has an HDR plane
+    pub fn set_linear(&mut self, x: u16, y: u16, color: LinearColor) {
+        assert!(x < self.width);
+        assert!(y < self.height);
+
+        let index = self.width as usize * y as usize + x as usize;
+        self.buffer[index] = color.to_srgb().into();
+
+        if let Some(hdr) = &mut self.hdr {
+            hdr[index] = color.rgb();
+        }
+    }
+
+    /// Pixel at an index in linear light, read from the HDR plane if the buffer has one
+    fn get_linear(&self, index: usize) -> LinearColor {
+        match &self.hdr {
+            Some(hdr) => {
+                let light = hdr[index];
+                LinearColor::new(light.r, light.g, light.b, 1.0)
+            }
+            None => LinearColor::from_srgb(Color::from(self.buffer[index])),
+        }
+    }
+
+    /// Combines a color with the pixel already at a position, weighted by the color's alpha.
+    /// Pixels are always opaque, so only the color channels are blended.
+    pub fn blend(&mut self, x: u16, y: u16, color: Color, blend_mode: BlendMode) {
+        assert!(x < self.width);
+        assert!(y < self.height);
+
+        let index = self.width as usize * y as usize + x as usize;
+        let destination = Color::from(self.buffer[index]);
+        let alpha = color.alpha();
+
+        let blended = match blend_mode {
+            BlendMode::Opaque => color,
+            BlendMode::SourceOver => destination.lerp(color, alpha),
+            BlendMode::Additive => destination + color * alpha,
+            BlendMode::Multiply => {
+                destination * (FloatColor::WHITE * (1.0 - alpha) + FloatColor::from(color) * alpha)
+            }
+        };
+
+        self.set_index(index, blended);
+    }
+
+    /// Combines a color in linear light with the pixel already at a position. The pixel is
+    /// decoded from sRGB before blending and encoded again afterwards.
+    pub fn blend_linear(&mut self, x: u16, y: u16, color: LinearColor, blend_mode: BlendMode) {
+        assert!(x < self.width);
+        assert!(y < self.height);
+
+        let index = self.width as usize * y as usize + x as usize;
+        let destination = self.get_linear(index);
+        let alpha = color.a.clamp(0.0, 1.0);
+
+        let blended = match blend_mode {
+            BlendMode::Opaque => color,
+            BlendMode::SourceOver => destination.lerp(color, alpha),
+            BlendMode::Additive => destination + color.rgb() * alpha,
+            BlendMode::Multiply => {
+                destination * (FloatColor::WHITE * (1.0 - alpha) + color.rgb() * alpha)
+            }
+        };
+
+        self.set_linear(x, y, blended);
+    }
+
+    /// Exposes and tone maps the HDR plane into the packed pixels, ready to be displayed or
+    /// written to a PNG. Buffers without an HDR plane are left as they are.
+    pub fn resolve_hdr(&mut self, settings: &HdrSettings) {
+        let Some(hdr) = &self.hdr else {
+            return;
+        };
+
+        for (pixel, &light) in self.buffer.iter_mut().zip(hdr.iter()) {
+            let mapped = settings.map(light);
+
+            *pixel = LinearColor::new(mapped.r, mapped.g, mapped.b, 1.0)
+                .to_srgb()
+                .into();
+        }
+    }
+
+    pub fn write_png<'a>(&self, path: &'a Path) -> Result<(), PngWriteError<'a>> {
+        let png_file =
+            File::create(path).map_err(|_| PngWriteError::WriteError(path.to_string_lossy()))?;
+
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(png_file),
+            self.width as u32,
+            self.height as u32,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder
+            .write_header()
+            .map_err(|_| PngWriteError::EncodeError)?;
+
+        let byte_buffer: Vec<u8> = self
+            .buffer
+            .iter()
+            .flat_map(|&pixel| {
+                let color = Color::from(pixel);
+                [color.r, color.g, color.b]
+            })
+            .collect();
+
+        writer
+            .write_image_data(&byte_buffer)
+            .map_err(|_| PngWriteError::EncodeError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdr::ToneMapping;
+
+    fn blend_onto(destination: Color, color: Color, blend_mode: BlendMode) -> Color {
+        let mut color_buffer = ColorBuffer::new(1, 1);
+        color_buffer.clear(destination);
+        color_buffer.blend(0, 0, color, blend_mode);
+
+        Color::from(color_buffer.buffer()[0])
+    }
+
+    #[test]
+    fn blend_modes_weight_color_by_alpha() {
+        let destination = Color::new(100, 100, 100);
+        let half_red = Color::rgba(255, 0, 0, 128);
+
+        assert_eq!(
+            blend_onto(destination, half_red, BlendMode::Opaque),
+            Color::new(255, 0, 0)
+        );
+        assert_eq!(
+            blend_onto(destination, half_red, BlendMode::SourceOver),
+            Color::new(177, 49, 49)
+        );
+        assert_eq!(
+            blend_onto(destination, half_red, BlendMode::Additive),
+            Color::new(228, 100, 100)
+        );
+        assert_eq!(
+            blend_onto(destination, half_red, BlendMode::Multiply),
+            Color::new(100, 49, 49)
+        );
+    }
+
+    #[test]
+    fn hdr_buffers_keep_light_brighter_than_white() {
+        let mut color_buffer = ColorBuffer::new_hdr(2, 1);
+        color_buffer.set_linear(0, 0, LinearColor::new(4.0, 1.0, 0.0, 1.0));
+        color_buffer.set_linear(1, 0, LinearColor::new(1.0, 1.0, 0.0, 1.0));
+
+        // Both pixels are clamped to the same color until they're tone mapped
+        assert_eq!(color_buffer.buffer()[0], color_buffer.buffer()[1]);
+
+        color_buffer.resolve_hdr(&HdrSettings {
+            tone_mapping: ToneMapping::Reinhard,
+            exposure: 0.0,
+        });
+
+        let bright = Color::from(color_buffer.buffer()[0]);
+        let dim = Color::from(color_buffer.buffer()[1]);
+        assert!(bright.r > dim.r);
+        assert_eq!(bright.g, dim.g);
+    }
+
+    #[test]
+    fn linear_blending_mixes_light_rather_than_encoded_values() {
+        let mut color_buffer = ColorBuffer::new(1, 1);
+        color_buffer.clear(Color::new(0, 0, 0));
+
+        let half_white = LinearColor::new(1.0, 1.0, 1.0, 0.5);
+        color_buffer.blend_linear(0, 0, half_white, BlendMode::SourceOver);
+
+        // Half of white's light is encoded brighter than halfway between black and white
+        assert_eq!(
+            Color::from(color_buffer.buffer()[0]),
+            Color::new(188, 188, 188)
+        );
     }
 }
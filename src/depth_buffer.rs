@@ -1,3 +1,6 @@
+use crate::tile::ScreenRect;
+
+#[derive(Debug)]
 pub struct DepthBuffer {
     buffer: Vec<f32>,
     width: u16,
@@ -13,6 +16,38 @@ impl DepthBuffer {
         }
     }
 
+    /// Copies the part of the buffer covered by a tile into a buffer of its own
+    pub(crate) fn copy_tile(&self, rect: ScreenRect) -> Self {
+        let mut buffer = Vec::with_capacity(rect.width as usize * rect.height as usize);
+
+        for row in rect.y..rect.y + rect.height {
+            let start = self.width as usize * row as usize + rect.x as usize;
+            buffer.extend_from_slice(&self.buffer[start..start + rect.width as usize]);
+        }
+
+        Self {
+            buffer,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+
+    /// Copies a tile's depths back into the part of the buffer it covers
+    pub(crate) fn write_tile(&mut self, rect: ScreenRect, tile: &DepthBuffer) {
+        for (row, depths) in tile.buffer.chunks_exact(tile.width as usize).enumerate() {
+            let start = self.width as usize * (rect.y as usize + row) + rect.x as usize;
+            self.buffer[start..start + depths.len()].copy_from_slice(depths);
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
     pub fn buffer(&self) -> &[f32] {
         &self.buffer
     }
@@ -1,39 +1,17 @@
 use std::ops::{Add, Sub};
 
-use crate::color::Color;
+use crate::color::{Color, LinearColor};
 use crate::color_buffer::ColorBuffer;
 use crate::depth_buffer::DepthBuffer;
 use crate::fixed::FixedI32;
+use crate::lighting::{perturb_normal, PixelLighting};
+use crate::material::BlendMode;
+use crate::shader::{Fragment, FragmentShader, ShadedTriangle, Varyings};
+use crate::simd::{EdgeSpan, LANES};
 use crate::texture::Texture;
-use crate::triangle::Triangle;
-
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct Vec2I {
-    x: i32,
-    y: i32,
-}
-
-impl Vec2I {
-    fn new(x: i32, y: i32) -> Self {
-        Self { x, y }
-    }
-}
-
-impl Add<Vec2I> for Vec2I {
-    type Output = Self;
-
-    fn add(self, rhs: Vec2I) -> Self::Output {
-        Self::new(self.x + rhs.x, self.y + rhs.y)
-    }
-}
-
-impl Sub<Vec2I> for Vec2I {
-    type Output = Self;
-
-    fn sub(self, rhs: Vec2I) -> Self::Output {
-        Self::new(self.x - rhs.x, self.y - rhs.y)
-    }
-}
+use crate::tile::ScreenRect;
+use crate::triangle::{bounding_box, Triangle, VertexData};
+use crate::vector::{Vec3, Vec4};
 
 #[derive(Clone, Copy, Debug)]
 pub struct RasterPoint {
@@ -96,6 +74,21 @@ impl Sub<RasterPoint> for RasterPoint {
     }
 }
 
+/// How the pixels of a triangle are lit and written to the buffers
+#[derive(Clone, Copy)]
+pub struct PixelState<'a> {
+    pub pixel_lighting: Option<PixelLighting<'a>>,
+    pub blend_mode: BlendMode,
+    /// Multiplies the alpha of every pixel
+    pub opacity: f32,
+    /// Pixels with an alpha below this are discarded
+    pub alpha_cutoff: f32,
+    /// Evaluate the triangle's edges for several pixels at once
+    pub simd: bool,
+    /// Light and blend in linear space, encoding the result as sRGB when it's written
+    pub linear: bool,
+}
+
 impl ColorBuffer {
     pub fn draw_grid(&mut self) {
         for x in (0..self.width()).step_by(10) {
@@ -159,129 +152,482 @@ impl ColorBuffer {
         self.draw_line(c, a, color);
     }
 
-    fn rasterize_triangle<F: FnMut(u16, u16, f32, f32, f32, &mut Self)>(
+    pub fn draw_filled_triangle(
         &mut self,
         triangle: &Triangle,
-        mut fill: F,
+        color: Color,
+        depth_buffer: &mut DepthBuffer,
+        pixel_state: PixelState,
     ) {
-        let (min_x, min_y, max_x, max_y) = triangle.bounding_box();
-        let min_x = min_x.floor().max(0.0) as u16;
-        let min_y = min_y.floor().max(0.0) as u16;
-        let max_x = max_x.ceil().min((self.width() - 1) as f32) as u16;
-        let max_y = max_y.ceil().min((self.height() - 1) as f32) as u16;
+        let rect = self.screen_rect();
 
-        let a = RasterPoint::new(
-            FixedI32::from_f32_lossy(triangle.points[0].x),
-            FixedI32::from_f32_lossy(triangle.points[0].y),
-        );
-
-        let b = RasterPoint::new(
-            FixedI32::from_f32_lossy(triangle.points[1].x),
-            FixedI32::from_f32_lossy(triangle.points[1].y),
-        );
-
-        let c = RasterPoint::new(
-            FixedI32::from_f32_lossy(triangle.points[2].x),
-            FixedI32::from_f32_lossy(triangle.points[2].y),
-        );
+        let fill = |x, y, alpha, beta, gamma| {
+            let interpolated_reciprocal_w = triangle.interpolate_reciprocal_w(alpha, beta, gamma);
 
-        let area = (b - a).cross(c - a);
+            if 1.0 - interpolated_reciprocal_w < depth_buffer.get(x, y) {
+                let depth = 1.0 - interpolated_reciprocal_w;
+
+                if pixel_state.linear {
+                    let color = shade_linear_pixel(
+                        triangle,
+                        LinearColor::from_srgb(color),
+                        pixel_state.pixel_lighting,
+                        alpha,
+                        beta,
+                        gamma,
+                        interpolated_reciprocal_w,
+                    );
+
+                    self.write_linear_pixel(depth_buffer, x, y, depth, color, &pixel_state);
+                } else {
+                    let color = shade_pixel(
+                        triangle,
+                        color,
+                        pixel_state.pixel_lighting,
+                        alpha,
+                        beta,
+                        gamma,
+                        interpolated_reciprocal_w,
+                    );
+
+                    self.write_pixel(depth_buffer, x, y, depth, color, &pixel_state);
+                }
+            }
+        };
 
-        let bias_1 = RasterPoint::edge_orientation(a, b);
-        let bias_2 = RasterPoint::edge_orientation(b, c);
-        let bias_3 = RasterPoint::edge_orientation(c, a);
+        rasterize_triangle(&triangle.points, rect, pixel_state.simd, fill);
+    }
 
-        let delta_w0_x = a.y - b.y;
-        let delta_w0_y = b.x - a.x;
+    pub fn draw_textured_triangle(
+        &mut self,
+        triangle: &Triangle,
+        texture: &Texture,
+        depth_buffer: &mut DepthBuffer,
+        flip_v: bool,
+        mipmapping: bool,
+        pixel_state: PixelState,
+    ) {
+        let rect = self.screen_rect();
+        let (gradient_x, gradient_y) = triangle.barycentric_gradients();
 
-        let delta_w1_x = b.y - c.y;
-        let delta_w1_y = c.x - b.x;
+        let uv_at = |alpha, beta, gamma| {
+            let reciprocal_w = triangle.interpolate_reciprocal_w(alpha, beta, gamma);
+            triangle.interpolate(triangle.tex_coords, alpha, beta, gamma, reciprocal_w)
+        };
 
-        let delta_w2_x = c.y - a.y;
-        let delta_w2_y = a.x - c.x;
+        let fill = |x, y, alpha, beta, gamma| {
+            let interpolated_reciprocal_w = triangle.interpolate_reciprocal_w(alpha, beta, gamma);
 
-        let p0 = RasterPoint::new(
-            FixedI32::from(min_x) + FixedI32::HALF_PIXEL,
-            FixedI32::from(min_y) + FixedI32::HALF_PIXEL,
-        );
-        let mut w0_row = p0.edge_weight(a, b, bias_1);
-        let mut w1_row = p0.edge_weight(b, c, bias_2);
-        let mut w2_row = p0.edge_weight(c, a, bias_3);
+            if 1.0 - interpolated_reciprocal_w < depth_buffer.get(x, y) {
+                let mut p_uv = triangle.interpolate(
+                    triangle.tex_coords,
+                    alpha,
+                    beta,
+                    gamma,
+                    interpolated_reciprocal_w,
+                );
+
+                // Pick a mip level from how far the UVs move to the neighbouring pixels
+                let level_of_detail = mipmapping.then(|| {
+                    let uv_dx = uv_at(
+                        alpha + gradient_x[0],
+                        beta + gradient_x[1],
+                        gamma + gradient_x[2],
+                    ) - p_uv;
+                    let uv_dy = uv_at(
+                        alpha + gradient_y[0],
+                        beta + gradient_y[1],
+                        gamma + gradient_y[2],
+                    ) - p_uv;
+
+                    texture.level_of_detail(uv_dx, uv_dy)
+                });
+
+                if flip_v {
+                    p_uv.v = 1.0 - p_uv.v;
+                }
 
-        for y in min_y..=max_y {
-            let mut w0 = w0_row;
-            let mut w1 = w1_row;
-            let mut w2 = w2_row;
+                let depth = 1.0 - interpolated_reciprocal_w;
+
+                if pixel_state.linear {
+                    let texel = match level_of_detail {
+                        Some(level_of_detail) => {
+                            texture.sample_trilinear_linear(p_uv, level_of_detail)
+                        }
+                        None => texture.sample_linear(p_uv),
+                    };
+
+                    let color = shade_linear_pixel(
+                        triangle,
+                        texel * LinearColor::from_srgb(triangle.color),
+                        pixel_state.pixel_lighting,
+                        alpha,
+                        beta,
+                        gamma,
+                        interpolated_reciprocal_w,
+                    );
+
+                    self.write_linear_pixel(depth_buffer, x, y, depth, color, &pixel_state);
+                } else {
+                    let texel = match level_of_detail {
+                        Some(level_of_detail) => texture.sample_trilinear(p_uv, level_of_detail),
+                        None => texture.sample(p_uv),
+                    };
+
+                    let color = shade_pixel(
+                        triangle,
+                        texel * triangle.color,
+                        pixel_state.pixel_lighting,
+                        alpha,
+                        beta,
+                        gamma,
+                        interpolated_reciprocal_w,
+                    );
+
+                    self.write_pixel(depth_buffer, x, y, depth, color, &pixel_state);
+                }
+            }
+        };
 
-            for x in min_x..=max_x {
-                if w0 >= FixedI32::ZERO && w1 >= FixedI32::ZERO && w2 >= FixedI32::ZERO {
-                    let alpha = f32::from(w1) / f32::from(area);
-                    let beta = f32::from(w2) / f32::from(area);
-                    let gamma = f32::from(w0) / f32::from(area);
+        rasterize_triangle(&triangle.points, rect, pixel_state.simd, fill);
+    }
 
-                    fill(x, y, alpha, beta, gamma, self);
+    /// Draws a triangle output by a vertex shader, coloring each pixel that passes the depth
+    /// test with a fragment shader
+    pub fn draw_shaded_triangle<V: Varyings, F: FragmentShader<V>>(
+        &mut self,
+        triangle: &ShadedTriangle<V>,
+        shader: &F,
+        depth_buffer: &mut DepthBuffer,
+        pixel_state: PixelState,
+    ) {
+        let rect = self.screen_rect();
+
+        let fill = |x, y, alpha, beta, gamma| {
+            let interpolated_reciprocal_w = triangle.interpolate_reciprocal_w(alpha, beta, gamma);
+            let depth = 1.0 - interpolated_reciprocal_w;
+
+            if depth < depth_buffer.get(x, y) {
+                let fragment = Fragment {
+                    x: rect.x + x,
+                    y: rect.y + y,
+                    depth,
+                    varyings: triangle.interpolate_varyings(
+                        alpha,
+                        beta,
+                        gamma,
+                        interpolated_reciprocal_w,
+                    ),
+                };
+
+                if let Some(color) = shader.shade_fragment(&fragment) {
+                    self.write_pixel(depth_buffer, x, y, depth, color, &pixel_state);
                 }
-
-                w0 += delta_w0_x;
-                w1 += delta_w1_x;
-                w2 += delta_w2_x;
             }
+        };
 
-            w0_row += delta_w0_y;
-            w1_row += delta_w1_y;
-            w2_row += delta_w2_y;
-        }
+        rasterize_triangle(&triangle.points, rect, pixel_state.simd, fill);
     }
 
-    pub fn draw_filled_triangle(
+    /// Writes a shaded pixel, or discards it if its alpha is below the cutoff. Opaque pixels
+    /// also write their depth, while blended pixels leave the depth buffer untouched so
+    /// surfaces behind them can still be seen.
+    fn write_pixel(
         &mut self,
-        triangle: &Triangle,
-        color: Color,
         depth_buffer: &mut DepthBuffer,
+        x: u16,
+        y: u16,
+        depth: f32,
+        color: Color,
+        pixel_state: &PixelState,
     ) {
-        let fill = |x, y, alpha, beta, gamma, color_buffer: &mut Self| {
-            let interpolated_reciprocal_w = 1.0 / triangle.points[0].w * alpha
-                + 1.0 / triangle.points[1].w * beta
-                + 1.0 / triangle.points[2].w * gamma;
-
-            if 1.0 - interpolated_reciprocal_w < depth_buffer.get(x, y) {
-                color_buffer.set(x, y, color);
-                depth_buffer.set(x, y, 1.0 - interpolated_reciprocal_w);
-            }
+        let color = Color {
+            a: (color.a as f32 * pixel_state.opacity) as u8,
+            ..color
         };
 
-        self.rasterize_triangle(triangle, fill);
+        if color.alpha() < pixel_state.alpha_cutoff {
+            return;
+        }
+
+        match pixel_state.blend_mode {
+            BlendMode::Opaque => {
+                self.set(x, y, color);
+                depth_buffer.set(x, y, depth);
+            }
+            blend_mode if pixel_state.linear => {
+                self.blend_linear(x, y, LinearColor::from_srgb(color), blend_mode);
+            }
+            blend_mode => self.blend(x, y, color, blend_mode),
+        }
     }
 
-    pub fn draw_textured_triangle(
+    /// Writes a pixel shaded in linear space, encoding it as sRGB. Like `write_pixel`, blended
+    /// pixels don't write their depth.
+    fn write_linear_pixel(
         &mut self,
-        triangle: &Triangle,
-        texture: &Texture,
         depth_buffer: &mut DepthBuffer,
-        flip_v: bool,
+        x: u16,
+        y: u16,
+        depth: f32,
+        color: LinearColor,
+        pixel_state: &PixelState,
     ) {
-        let fill = |x, y, alpha, beta, gamma, color_buffer: &mut Self| {
-            let interpolated_reciprocal_w = 1.0 / triangle.points[0].w * alpha
-                + 1.0 / triangle.points[1].w * beta
-                + 1.0 / triangle.points[2].w * gamma;
-
-            let mut p_uv = ((triangle.tex_coords[0] / triangle.points[0].w * alpha)
-                + (triangle.tex_coords[1] / triangle.points[1].w * beta)
-                + (triangle.tex_coords[2] / triangle.points[2].w * gamma))
-                / interpolated_reciprocal_w;
-
-            if flip_v {
-                p_uv.v = 1.0 - p_uv.v;
+        let color = LinearColor {
+            a: color.a * pixel_state.opacity,
+            ..color
+        };
+
+        if color.a < pixel_state.alpha_cutoff {
+            return;
+        }
+
+        match pixel_state.blend_mode {
+            BlendMode::Opaque => {
+                self.set_linear(x, y, color);
+                depth_buffer.set(x, y, depth);
+            }
+            blend_mode => self.blend_linear(x, y, color, blend_mode),
+        }
+    }
+}
+
+impl DepthBuffer {
+    /// Draws a triangle into the depth buffer only, keeping the closest depth at each pixel.
+    /// Depth is read from the z of each point and interpolated perspective-correctly using w.
+    /// Unlike the color buffer's triangles, both windings are drawn.
+    pub fn draw_depth_triangle(&mut self, points: &[Vec4; 3]) {
+        let rect = ScreenRect::new(0, 0, self.width(), self.height());
+
+        let [a, b, c] = *points;
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        let points = &if area < 0.0 { [a, c, b] } else { [a, b, c] };
+
+        rasterize_triangle(points, rect, true, |x, y, alpha, beta, gamma| {
+            let reciprocal_w = alpha / points[0].w + beta / points[1].w + gamma / points[2].w;
+            let depth = (alpha * points[0].z / points[0].w
+                + beta * points[1].z / points[1].w
+                + gamma * points[2].z / points[2].w)
+                / reciprocal_w;
+
+            if depth < self.get(x, y) {
+                self.set(x, y, depth);
             }
+        });
+    }
+}
 
-            let color = texture.sample(p_uv) * triangle.color;
+/// Light a pixel's surface color, either per pixel from the interpolated normal and
+/// position, or from the light values interpolated between the triangle's vertices. The
+/// surface color is first tinted by the interpolated vertex color.
+fn shade_pixel(
+    triangle: &Triangle,
+    color: Color,
+    pixel_lighting: Option<PixelLighting>,
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+    reciprocal_w: f32,
+) -> Color {
+    let vertex = VertexData::from(triangle.interpolate(
+        triangle.attributes,
+        alpha,
+        beta,
+        gamma,
+        reciprocal_w,
+    ));
+    let color = color * Color::from(vertex.color);
+
+    match pixel_lighting {
+        Some(pixel_lighting) => {
+            let normal = pixel_normal(
+                triangle,
+                &vertex,
+                &pixel_lighting,
+                alpha,
+                beta,
+                gamma,
+                reciprocal_w,
+            );
+
+            pixel_lighting.shade(color, normal, vertex.world_pos)
+        }
+        None => color * vertex.light,
+    }
+}
 
-            if 1.0 - interpolated_reciprocal_w < depth_buffer.get(x, y) {
-                color_buffer.set(x, y, color);
-                depth_buffer.set(x, y, 1.0 - interpolated_reciprocal_w);
+/// Same as `shade_pixel`, but with the surface and vertex colors decoded into linear light
+fn shade_linear_pixel(
+    triangle: &Triangle,
+    color: LinearColor,
+    pixel_lighting: Option<PixelLighting>,
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+    reciprocal_w: f32,
+) -> LinearColor {
+    let vertex = VertexData::from(triangle.interpolate(
+        triangle.attributes,
+        alpha,
+        beta,
+        gamma,
+        reciprocal_w,
+    ));
+    let color = color * LinearColor::from_srgb(Color::from(vertex.color));
+
+    match pixel_lighting {
+        Some(pixel_lighting) => {
+            let normal = pixel_normal(
+                triangle,
+                &vertex,
+                &pixel_lighting,
+                alpha,
+                beta,
+                gamma,
+                reciprocal_w,
+            );
+
+            pixel_lighting.shade_linear(color, normal, vertex.world_pos)
+        }
+        None => color * vertex.light,
+    }
+}
+
+/// Normal of a pixel lit per pixel, from the vertex data interpolated at it. The
+/// interpolated normal is bent by the normal map if there is one.
+fn pixel_normal(
+    triangle: &Triangle,
+    vertex: &VertexData,
+    pixel_lighting: &PixelLighting,
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+    reciprocal_w: f32,
+) -> Vec3 {
+    let mut normal = vertex.normal.normalized_or_zero();
+
+    if let Some(normal_map) = pixel_lighting.normal_map {
+        let mut uv = triangle.interpolate(triangle.tex_coords, alpha, beta, gamma, reciprocal_w);
+        if pixel_lighting.flip_v {
+            uv.v = 1.0 - uv.v;
+        }
+
+        normal = perturb_normal(
+            normal,
+            vertex.tangent,
+            vertex.bitangent,
+            normal_map.sample(uv),
+        );
+    }
+
+    normal
+}
+
+/// Calls `fill` with the position and barycentric coordinates of every pixel covered by a
+/// triangle, given its points in screen space. Only pixels inside `rect` are filled, and
+/// their positions are given relative to its top left corner. With `simd` set, edge weights
+/// are evaluated for several pixels at once, which gives the same results.
+fn rasterize_triangle<F: FnMut(u16, u16, f32, f32, f32)>(
+    points: &[Vec4; 3],
+    rect: ScreenRect,
+    simd: bool,
+    mut fill: F,
+) {
+    let (min_x, min_y, max_x, max_y) = bounding_box(points);
+    let min_x = min_x.floor().max(0.0) as u16;
+    let min_y = min_y.floor().max(0.0) as u16;
+    let max_x = max_x.ceil().min((rect.x + rect.width - 1) as f32) as u16;
+    let max_y = max_y.ceil().min((rect.y + rect.height - 1) as f32) as u16;
+
+    // Pixels are always stepped to from the corner of the bounding box, so a triangle covers
+    // exactly the same pixels with the same weights however the screen is split into tiles
+    let start_x = min_x.max(rect.x);
+    let start_y = min_y.max(rect.y);
+
+    let a = RasterPoint::new(
+        FixedI32::from_f32_lossy(points[0].x),
+        FixedI32::from_f32_lossy(points[0].y),
+    );
+
+    let b = RasterPoint::new(
+        FixedI32::from_f32_lossy(points[1].x),
+        FixedI32::from_f32_lossy(points[1].y),
+    );
+
+    let c = RasterPoint::new(
+        FixedI32::from_f32_lossy(points[2].x),
+        FixedI32::from_f32_lossy(points[2].y),
+    );
+
+    let area = (b - a).cross(c - a);
+
+    let bias_1 = RasterPoint::edge_orientation(a, b);
+    let bias_2 = RasterPoint::edge_orientation(b, c);
+    let bias_3 = RasterPoint::edge_orientation(c, a);
+
+    let delta_w0_x = a.y - b.y;
+    let delta_w0_y = b.x - a.x;
+
+    let delta_w1_x = b.y - c.y;
+    let delta_w1_y = c.x - b.x;
+
+    let delta_w2_x = c.y - a.y;
+    let delta_w2_y = a.x - c.x;
+
+    let p0 = RasterPoint::new(
+        FixedI32::from(min_x) + FixedI32::HALF_PIXEL,
+        FixedI32::from(min_y) + FixedI32::HALF_PIXEL,
+    );
+    let (skip_x, skip_y) = ((start_x - min_x) as i32, (start_y - min_y) as i32);
+    let mut w0_row = p0.edge_weight(a, b, bias_1) + delta_w0_x * skip_x + delta_w0_y * skip_y;
+    let mut w1_row = p0.edge_weight(b, c, bias_2) + delta_w1_x * skip_x + delta_w1_y * skip_y;
+    let mut w2_row = p0.edge_weight(c, a, bias_3) + delta_w2_x * skip_x + delta_w2_y * skip_y;
+
+    let area_f32 = f32::from(area);
+    let steps = [delta_w0_x, delta_w1_x, delta_w2_x].map(FixedI32::to_bits);
+
+    for y in start_y..=max_y {
+        let mut w0 = w0_row;
+        let mut w1 = w1_row;
+        let mut w2 = w2_row;
+
+        if simd {
+            for span_x in (start_x as u32..=max_x as u32).step_by(LANES) {
+                let span = EdgeSpan::evaluate([w0, w1, w2].map(FixedI32::to_bits), steps, area_f32);
+                let lanes = LANES.min((max_x as u32 - span_x) as usize + 1);
+
+                for lane in (0..lanes).filter(|lane| span.coverage & (1 << lane) != 0) {
+                    fill(
+                        span_x as u16 + lane as u16 - rect.x,
+                        y - rect.y,
+                        span.alpha[lane],
+                        span.beta[lane],
+                        span.gamma[lane],
+                    );
+                }
+
+                w0 += delta_w0_x * LANES as i32;
+                w1 += delta_w1_x * LANES as i32;
+                w2 += delta_w2_x * LANES as i32;
             }
-        };
+        } else {
+            for x in start_x..=max_x {
+                if w0 >= FixedI32::ZERO && w1 >= FixedI32::ZERO && w2 >= FixedI32::ZERO {
+                    let alpha = f32::from(w1) / area_f32;
+                    let beta = f32::from(w2) / area_f32;
+                    let gamma = f32::from(w0) / area_f32;
+
+                    fill(x - rect.x, y - rect.y, alpha, beta, gamma);
+                }
+
+                w0 += delta_w0_x;
+                w1 += delta_w1_x;
+                w2 += delta_w2_x;
+            }
+        }
 
-        self.rasterize_triangle(triangle, fill);
+        w0_row += delta_w0_y;
+        w1_row += delta_w1_y;
+        w2_row += delta_w2_y;
     }
 }
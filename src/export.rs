@@ -0,0 +1,380 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::color::Color;
+use crate::matrix::Mat4;
+use crate::polygon::{Polygon, PolygonVertex};
+use crate::render::clip_to_frustum;
+use crate::scene::{Object, Scene};
+use crate::texture::Tex2;
+use crate::triangle::VertexData;
+use crate::vector::{Vec3, Vec4};
+
+pub enum ExportError<'a> {
+    WriteError(Cow<'a, str>),
+    /// The file's extension isn't one of the formats that can be exported
+    UnsupportedFormat(Cow<'a, str>),
+}
+
+impl std::fmt::Display for ExportError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WriteError(path) => {
+                write!(f, "could not write mesh file at {path}")
+            }
+            Self::UnsupportedFormat(path) => {
+                write!(f, "{path}: unsupported export format, expected OBJ or PLY")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Obj,
+    /// Binary little endian PLY
+    Ply,
+}
+
+impl ExportFormat {
+    /// Picks the format from a file's extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "obj" => Some(Self::Obj),
+            "ply" => Some(Self::Ply),
+            _ => None,
+        }
+    }
+}
+
+/// Geometry of an object with its world transform baked in. Every attribute has one entry
+/// per vertex.
+#[derive(Default)]
+struct BakedMesh {
+    positions: Vec<Vec3>,
+    uvs: Vec<Tex2>,
+    normals: Vec<Vec3>,
+    colors: Vec<Color>,
+    faces: Vec<[u32; 3]>,
+}
+
+impl BakedMesh {
+    fn push_vertex(&mut self, vertex: &PolygonVertex) -> u32 {
+        let data = VertexData::from(vertex.attributes);
+
+        self.positions.push(data.world_pos);
+        self.uvs.push(vertex.uv);
+        self.normals.push(data.normal.normalized_or_zero());
+        self.colors.push(Color::from(data.color));
+
+        self.positions.len() as u32 - 1
+    }
+}
+
+/// Writes every object in a scene to an OBJ or PLY file, with each mesh's translation,
+/// rotation and scale baked into its vertices. Given the projection matrix of the scene's
+/// camera, only the geometry inside its view frustum is written, cut along the frustum's
+/// planes.
+pub fn export_scene<'a>(
+    scene: &Scene,
+    path: &'a Path,
+    projection_matrix: Option<Mat4>,
+) -> Result<(), ExportError<'a>> {
+    let format = ExportFormat::from_path(path)
+        .ok_or(ExportError::UnsupportedFormat(path.to_string_lossy()))?;
+
+    let view_projection_matrix =
+        projection_matrix.map(|projection_matrix| projection_matrix * scene.camera.view_matrix());
+    let meshes: Vec<BakedMesh> = scene
+        .objects()
+        .iter()
+        .map(|object| bake_object(object, view_projection_matrix))
+        .collect();
+
+    // Vertex colors are only written if some mesh has them
+    let has_colors = scene
+        .objects()
+        .iter()
+        .any(|object| !object.mesh.vertex_colors.is_empty());
+
+    let write = || -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        match format {
+            ExportFormat::Obj => write_obj(&mut writer, &meshes, has_colors)?,
+            ExportFormat::Ply => write_ply(&mut writer, &meshes, has_colors)?,
+        }
+
+        writer.flush()
+    };
+
+    write().map_err(|_| ExportError::WriteError(path.to_string_lossy()))
+}
+
+fn bake_object(object: &Object, view_projection_matrix: Option<Mat4>) -> BakedMesh {
+    let mesh = &object.mesh;
+    let world_matrix = mesh.world_matrix();
+    let normal_matrix = mesh.normal_matrix();
+
+    let mut baked = BakedMesh::default();
+    // Corners sharing a position, UV and normal in the mesh share a vertex when exported
+    let mut shared_vertices: HashMap<(u32, u32, u32), u32> = HashMap::new();
+
+    for face in mesh.faces.iter() {
+        let vertex_indices = [face.a, face.b, face.c];
+        let uv_indices = [face.a_uv, face.b_uv, face.c_uv];
+        let world_positions = vertex_indices
+            .map(|index| Vec3::from(world_matrix * Vec4::from(mesh.vertices[index as usize])));
+
+        // Faces without vertex normals get the face normal at each corner
+        let face_normal = (world_positions[1] - world_positions[0])
+            .cross(world_positions[2] - world_positions[0])
+            .normalized_or_zero();
+        let normals = match face.normals {
+            Some(normal_indices) => normal_indices.map(|index| {
+                let normal = mesh.vertex_normals[index as usize];
+                Vec3::from(normal_matrix * Vec4::new(normal.x, normal.y, normal.z, 0.0))
+            }),
+            None => [face_normal; 3],
+        };
+
+        let corners = [0, 1, 2].map(|corner| PolygonVertex {
+            pos: view_projection_matrix
+                .map(|matrix| matrix * Vec4::from(world_positions[corner]))
+                .unwrap_or_default(),
+            uv: mesh.vertex_uvs[uv_indices[corner] as usize],
+            attributes: VertexData {
+                normal: normals[corner],
+                world_pos: world_positions[corner],
+                color: mesh.vertex_color(vertex_indices[corner] as usize),
+                ..Default::default()
+            }
+            .into(),
+        });
+
+        if view_projection_matrix.is_some() {
+            let polygon = Polygon::new(corners.into_iter().collect());
+
+            for triangle in clip_to_frustum(&polygon).triangulate() {
+                let face = triangle.map(|vertex| baked.push_vertex(&vertex));
+                baked.faces.push(face);
+            }
+        } else {
+            let face = [0, 1, 2].map(|corner| match face.normals {
+                Some(normal_indices) => *shared_vertices
+                    .entry((
+                        vertex_indices[corner],
+                        uv_indices[corner],
+                        normal_indices[corner],
+                    ))
+                    .or_insert_with(|| baked.push_vertex(&corners[corner])),
+                None => baked.push_vertex(&corners[corner]),
+            });
+            baked.faces.push(face);
+        }
+    }
+
+    baked
+}
+
+fn write_obj(writer: &mut impl Write, meshes: &[BakedMesh], has_colors: bool) -> io::Result<()> {
+    writeln!(writer, "# Exported by software-renderer")?;
+
+    // Indices continue from one object to the next, and start at 1
+    let mut first_index = 1;
+
+    for (object_index, mesh) in meshes.iter().enumerate() {
+        writeln!(writer, "o object_{object_index}")?;
+
+        for (position, color) in mesh.positions.iter().zip(mesh.colors.iter()) {
+            write!(writer, "v {} {} {}", position.x, position.y, position.z)?;
+
+            if has_colors {
+                let [r, g, b] = [color.r, color.g, color.b].map(|channel| channel as f32 / 255.0);
+                write!(writer, " {r} {g} {b}")?;
+            }
+
+            writeln!(writer)?;
+        }
+
+        // Texture coordinates are flipped back, as they are when OBJ files are read
+        for uv in mesh.uvs.iter() {
+            writeln!(writer, "vt {} {}", uv.u, 1.0 - uv.v)?;
+        }
+
+        for normal in mesh.normals.iter() {
+            writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+
+        for face in mesh.faces.iter() {
+            let [a, b, c] = face.map(|index| index + first_index);
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+
+        first_index += mesh.positions.len() as u32;
+    }
+
+    Ok(())
+}
+
+fn write_ply(writer: &mut impl Write, meshes: &[BakedMesh], has_colors: bool) -> io::Result<()> {
+    let vertex_count: usize = meshes.iter().map(|mesh| mesh.positions.len()).sum();
+    let face_count: usize = meshes.iter().map(|mesh| mesh.faces.len()).sum();
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format binary_little_endian 1.0")?;
+    writeln!(writer, "comment Exported by software-renderer")?;
+    writeln!(writer, "element vertex {vertex_count}")?;
+    for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+        writeln!(writer, "property float {property}")?;
+    }
+    if has_colors {
+        for property in ["red", "green", "blue", "alpha"] {
+            writeln!(writer, "property uchar {property}")?;
+        }
+    }
+    writeln!(writer, "element face {face_count}")?;
+    writeln!(writer, "property list uchar uint vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    for mesh in meshes {
+        for index in 0..mesh.positions.len() {
+            let (position, normal, uv) =
+                (mesh.positions[index], mesh.normals[index], mesh.uvs[index]);
+
+            // Texture coordinates are flipped back, as they are when PLY files are read
+            for value in [
+                position.x,
+                position.y,
+                position.z,
+                normal.x,
+                normal.y,
+                normal.z,
+                uv.u,
+                1.0 - uv.v,
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+
+            if has_colors {
+                let color = mesh.colors[index];
+                writer.write_all(&[color.r, color.g, color.b, color.a])?;
+            }
+        }
+    }
+
+    // Indices continue from one object to the next
+    let mut first_index = 0;
+
+    for mesh in meshes {
+        for face in mesh.faces.iter() {
+            writer.write_all(&[3])?;
+            for index in face {
+                writer.write_all(&(index + first_index).to_le_bytes())?;
+            }
+        }
+
+        first_index += mesh.positions.len() as u32;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::material::Material;
+    use crate::mesh::Mesh;
+    use crate::texture::Texture;
+    use crate::triangle::Face;
+
+    /// A triangle moved away from the origin, so its transform has to be baked in
+    fn scene_with_triangle(translation: Vec3) -> Scene {
+        let mesh = Mesh::new(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            vec![
+                Tex2::new(0.0, 1.0),
+                Tex2::new(1.0, 1.0),
+                Tex2::new(0.0, 0.0),
+            ],
+            Vec::new(),
+            vec![Face::new(0, 1, 2, 0, 1, 2, Color::new(255, 255, 255))],
+            Vec3::default(),
+            Vec3::splat(2.0),
+            translation,
+        );
+
+        let mut scene = Scene::new(Camera::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+        ));
+        scene.add_object(Object::new(
+            mesh,
+            Texture::from_color(1, 1, Color::new(255, 255, 255)),
+            Material::default(),
+        ));
+
+        scene
+    }
+
+    #[test]
+    fn transforms_are_baked_into_obj_files() {
+        let scene = scene_with_triangle(Vec3::new(1.0, 2.0, 3.0));
+        let meshes: Vec<BakedMesh> = scene
+            .objects()
+            .iter()
+            .map(|object| bake_object(object, None))
+            .collect();
+
+        let mut obj = Vec::new();
+        write_obj(&mut obj, &meshes, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(obj).unwrap(),
+            "# Exported by software-renderer\n\
+             o object_0\n\
+             v 1 2 3\n\
+             v 3 2 3\n\
+             v 1 4 3\n\
+             vt 0 0\n\
+             vt 1 0\n\
+             vt 0 1\n\
+             vn 0 0 1\n\
+             vn 0 0 1\n\
+             vn 0 0 1\n\
+             f 1/1/1 2/2/2 3/3/3\n"
+        );
+    }
+
+    #[test]
+    fn frustum_exports_are_clipped() {
+        // Half of the triangle is to the right of the camera's view
+        let scene = scene_with_triangle(Vec3::new(4.0, -1.0, 0.0));
+        let projection_matrix = Mat4::projection(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+
+        let baked = bake_object(
+            &scene.objects()[0],
+            Some(projection_matrix * scene.camera.view_matrix()),
+        );
+
+        // The right frustum plane is at x = 5 at this depth
+        assert!(baked.faces.len() > 1);
+        assert!(baked
+            .positions
+            .iter()
+            .all(|position| position.x <= 5.0 + 1e-4));
+        assert!(baked.positions.iter().any(|position| position.x > 4.9));
+    }
+}
@@ -13,7 +13,12 @@ impl FixedI32 {
         Self((x * 256.0).round() as i32)
     }
 
-    pub fn to_i32_lossy(&self) -> i32 {
+    /// Underlying integer, scaled by 256
+    pub fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    pub fn to_i32_lossy(self) -> i32 {
         self.0 / 256
     }
 
@@ -80,6 +85,15 @@ impl Mul<FixedI32> for FixedI32 {
     }
 }
 
+/// Scales by a whole number, which unlike multiplying two fixed point values loses no precision
+impl Mul<i32> for FixedI32 {
+    type Output = FixedI32;
+
+    fn mul(self, rhs: i32) -> Self::Output {
+        Self(self.0 * rhs)
+    }
+}
+
 impl Div<FixedI32> for FixedI32 {
     type Output = FixedI32;
 
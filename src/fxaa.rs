@@ -0,0 +1,304 @@
+use crate::color::{Color, LinearColor};
+use crate::color_buffer::ColorBuffer;
+use crate::depth_buffer::DepthBuffer;
+use crate::post::PostEffect;
+
+/// How thoroughly FXAA looks for edges and follows them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FxaaQuality {
+    /// Skips low contrast edges and takes long steps along edges, which can leave the ends of
+    /// long edges jagged
+    Low,
+    Medium,
+    /// Smooths low contrast edges too, following edges one pixel at a time to start with
+    High,
+}
+
+impl FxaaQuality {
+    /// Contrast an edge needs, relative to the brightest pixel around it, to be smoothed
+    fn edge_threshold(self) -> f32 {
+        match self {
+            Self::Low => 0.25,
+            Self::Medium => 0.166,
+            Self::High => 0.125,
+        }
+    }
+
+    /// Contrast below which dark edges are left alone, however low their relative contrast
+    fn edge_threshold_min(self) -> f32 {
+        match self {
+            Self::Low => 0.0833,
+            Self::Medium => 0.0625,
+            Self::High => 0.0312,
+        }
+    }
+
+    /// How much pixels thinner than a pixel are blended with their neighbors
+    fn subpixel_blend(self) -> f32 {
+        match self {
+            Self::Low => 0.5,
+            Self::Medium => 0.75,
+            Self::High => 0.75,
+        }
+    }
+
+    /// Distances in pixels taken by each step when following an edge to its ends
+    fn search_steps(self) -> &'static [f32] {
+        match self {
+            Self::Low => &[1.0, 1.5, 2.0, 4.0, 12.0],
+            Self::Medium => &[1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0],
+            Self::High => &[1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0],
+        }
+    }
+
+    /// The preset after this one, or `None` after the highest
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Low => Some(Self::Medium),
+            Self::Medium => Some(Self::High),
+            Self::High => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FxaaQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+/// Fast approximate anti-aliasing. Finds edges from the contrast in brightness between
+/// pixels, follows each edge to its ends to estimate where it crosses the pixel, and blends
+/// the pixel with its neighbor across the edge by that much.
+#[derive(Clone, Copy, Debug)]
+pub struct Fxaa {
+    pub quality: FxaaQuality,
+}
+
+/// Perceived brightness of an sRGB color, from 0 to 1
+fn luma(color: Color) -> f32 {
+    (0.299 * color.r as f32 + 0.587 * color.g as f32 + 0.114 * color.b as f32) / 255.0
+}
+
+/// Brightness of each pixel of a frame, with pixels past the edges repeating the edge pixels
+struct LumaPlane {
+    luma: Vec<f32>,
+    width: usize,
+    height: usize,
+}
+
+impl LumaPlane {
+    fn get(&self, x: isize, y: isize) -> f32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+
+        self.luma[y * self.width + x]
+    }
+
+    /// Bilinearly samples the brightness, with pixel centers at whole coordinates
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let top = self.get(x0, y0) + (self.get(x0 + 1, y0) - self.get(x0, y0)) * tx;
+        let bottom = self.get(x0, y0 + 1) + (self.get(x0 + 1, y0 + 1) - self.get(x0, y0 + 1)) * tx;
+
+        top + (bottom - top) * ty
+    }
+}
+
+impl Fxaa {
+    /// How far across the edge the pixel at x, y should be blended, in pixels along the x
+    /// axis for vertical edges or the y axis for horizontal ones. Returns `None` for pixels
+    /// that aren't on an edge.
+    fn blend_offset(&self, plane: &LumaPlane, x: isize, y: isize) -> Option<(f32, bool)> {
+        let center = plane.get(x, y);
+        let up = plane.get(x, y - 1);
+        let down = plane.get(x, y + 1);
+        let left = plane.get(x - 1, y);
+        let right = plane.get(x + 1, y);
+
+        let luma_max = center.max(up).max(down).max(left).max(right);
+        let luma_min = center.min(up).min(down).min(left).min(right);
+        let range = luma_max - luma_min;
+
+        if range
+            < self
+                .quality
+                .edge_threshold_min()
+                .max(luma_max * self.quality.edge_threshold())
+        {
+            return None;
+        }
+
+        let up_left = plane.get(x - 1, y - 1);
+        let up_right = plane.get(x + 1, y - 1);
+        let down_left = plane.get(x - 1, y + 1);
+        let down_right = plane.get(x + 1, y + 1);
+
+        // Blend pixels that stand out from all of their neighbors, such as thin lines, by
+        // how much they stand out
+        let neighborhood =
+            (2.0 * (up + down + left + right) + up_left + up_right + down_left + down_right) / 12.0;
+        let subpixel = ((neighborhood - center).abs() / range).clamp(0.0, 1.0);
+        let subpixel = subpixel * subpixel * (3.0 - 2.0 * subpixel);
+        let subpixel_offset = subpixel * subpixel * self.quality.subpixel_blend();
+
+        // Edges run along the direction with the least change in brightness
+        let horizontal_change = (up_left + down_left - 2.0 * left).abs()
+            + 2.0 * (up + down - 2.0 * center).abs()
+            + (up_right + down_right - 2.0 * right).abs();
+        let vertical_change = (up_left + up_right - 2.0 * up).abs()
+            + 2.0 * (left + right - 2.0 * center).abs()
+            + (down_left + down_right - 2.0 * down).abs();
+        let horizontal = horizontal_change >= vertical_change;
+
+        // Pick the side of the pixel the edge is on
+        let (before, after) = if horizontal {
+            (up, down)
+        } else {
+            (left, right)
+        };
+        let gradient_before = (before - center).abs();
+        let gradient_after = (after - center).abs();
+
+        let (step, edge_luma) = if gradient_before >= gradient_after {
+            (-1.0, 0.5 * (before + center))
+        } else {
+            (1.0, 0.5 * (after + center))
+        };
+        let gradient_threshold = 0.25 * gradient_before.max(gradient_after);
+
+        // Follow the edge both ways, halfway between this pixel and its neighbor across it,
+        // until the brightness no longer matches the edge
+        let sample_along = |distance: f32| {
+            if horizontal {
+                plane.sample(x as f32 + distance, y as f32 + 0.5 * step)
+            } else {
+                plane.sample(x as f32 + 0.5 * step, y as f32 + distance)
+            }
+        };
+
+        let mut distance_back = 0.0;
+        let mut distance_forward = 0.0;
+        let mut end_back = 0.0;
+        let mut end_forward = 0.0;
+        let mut reached_back = false;
+        let mut reached_forward = false;
+
+        for &search_step in self.quality.search_steps() {
+            if !reached_back {
+                distance_back += search_step;
+                end_back = sample_along(-distance_back) - edge_luma;
+                reached_back = end_back.abs() >= gradient_threshold;
+            }
+            if !reached_forward {
+                distance_forward += search_step;
+                end_forward = sample_along(distance_forward) - edge_luma;
+                reached_forward = end_forward.abs() >= gradient_threshold;
+            }
+
+            if reached_back && reached_forward {
+                break;
+            }
+        }
+
+        // The closer end decides how far the edge is from this pixel. Pixels are only
+        // blended if the brightness at that end changes the opposite way to this pixel, as
+        // otherwise the edge turns away from it.
+        let (closest_distance, closest_end) = if distance_back < distance_forward {
+            (distance_back, end_back)
+        } else {
+            (distance_forward, end_forward)
+        };
+        let edge_offset = if (closest_end < 0.0) != (center < edge_luma) {
+            0.5 - closest_distance / (distance_back + distance_forward)
+        } else {
+            0.0
+        };
+
+        Some((edge_offset.max(subpixel_offset) * step, horizontal))
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn apply(&self, color_buffer: &mut ColorBuffer, _depth_buffer: &DepthBuffer) {
+        let width = color_buffer.width() as usize;
+        let height = color_buffer.height() as usize;
+        let source = color_buffer.buffer().to_vec();
+
+        let plane = LumaPlane {
+            luma: source
+                .iter()
+                .map(|&pixel| luma(Color::from(pixel)))
+                .collect(),
+            width,
+            height,
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let Some((offset, horizontal)) = self.blend_offset(&plane, x as isize, y as isize)
+                else {
+                    continue;
+                };
+
+                // Blend towards the neighbor on the side the offset points to
+                let (neighbor_x, neighbor_y) = match (horizontal, offset < 0.0) {
+                    (true, true) => (x, y.saturating_sub(1)),
+                    (true, false) => (x, (y + 1).min(height - 1)),
+                    (false, true) => (x.saturating_sub(1), y),
+                    (false, false) => ((x + 1).min(width - 1), y),
+                };
+
+                let i = y * width + x;
+                let color = LinearColor::from_srgb(Color::from(source[i]));
+                let neighbor =
+                    LinearColor::from_srgb(Color::from(source[neighbor_y * width + neighbor_x]));
+
+                color_buffer.set_index(i, color.lerp(neighbor, offset.abs()).to_srgb());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staircase_edges_are_smoothed() {
+        let mut color_buffer = ColorBuffer::new(16, 16);
+        let depth_buffer = DepthBuffer::new(16, 16);
+        color_buffer.clear(Color::new(0, 0, 0));
+
+        // A shallow white ramp with one step every four pixels
+        for x in 0..16 {
+            for y in (x / 4) + 6..16 {
+                color_buffer.set(x, y, Color::new(0xFF, 0xFF, 0xFF));
+            }
+        }
+        let aliased = color_buffer.buffer().to_vec();
+
+        Fxaa {
+            quality: FxaaQuality::High,
+        }
+        .apply(&mut color_buffer, &depth_buffer);
+
+        // Pixels along the edge get gray values between black and white, while the flat areas
+        // away from it are left alone
+        let is_gray = |pixel: u32| {
+            let color = Color::from(pixel);
+            color.r > 0 && color.r < 0xFF
+        };
+        assert!(color_buffer.buffer().iter().any(|&pixel| is_gray(pixel)));
+        assert!(!aliased.iter().any(|&pixel| is_gray(pixel)));
+        assert_eq!(color_buffer.buffer()[0], aliased[0]);
+        assert_eq!(color_buffer.buffer()[255], aliased[255]);
+    }
+}
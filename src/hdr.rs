@@ -0,0 +1,119 @@
+use crate::color::FloatColor;
+
+/// How colors brighter than full intensity are brought into the range that can be displayed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Channels brighter than full intensity are cut off, as if there were no HDR buffer
+    Clamp,
+    /// c / (1 + c), which never quite reaches full intensity
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with a toe in the shadows and a soft
+    /// shoulder in the highlights
+    Aces,
+}
+
+impl ToneMapping {
+    /// Maps a color in linear light into the 0 to 1 range
+    pub fn apply(self, color: FloatColor) -> FloatColor {
+        let map_channel = |value: f32| match self {
+            Self::Clamp => value.clamp(0.0, 1.0),
+            Self::Reinhard => {
+                let value = value.max(0.0);
+                value / (1.0 + value)
+            }
+            Self::Aces => {
+                let value = value.max(0.0);
+                let mapped =
+                    (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14);
+                mapped.clamp(0.0, 1.0)
+            }
+        };
+
+        FloatColor::new(
+            map_channel(color.r),
+            map_channel(color.g),
+            map_channel(color.b),
+        )
+    }
+
+    /// The tone mapper after this one, wrapping around to the first
+    pub fn next(self) -> Self {
+        match self {
+            Self::Clamp => Self::Reinhard,
+            Self::Reinhard => Self::Aces,
+            Self::Aces => Self::Clamp,
+        }
+    }
+}
+
+impl std::fmt::Display for ToneMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Clamp => write!(f, "clamp"),
+            Self::Reinhard => write!(f, "Reinhard"),
+            Self::Aces => write!(f, "ACES filmic"),
+        }
+    }
+}
+
+/// Settings for resolving an HDR color buffer into displayable colors
+#[derive(Clone, Copy, Debug)]
+pub struct HdrSettings {
+    pub tone_mapping: ToneMapping,
+    /// Exposure in stops. Each stop doubles the brightness of the image before it's tone
+    /// mapped.
+    pub exposure: f32,
+}
+
+impl HdrSettings {
+    /// Exposes and tone maps a color in linear light
+    pub fn map(&self, color: FloatColor) -> FloatColor {
+        self.tone_mapping.apply(color * self.exposure.exp2())
+    }
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            tone_mapping: ToneMapping::Clamp,
+            exposure: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_mappers_keep_bright_colors_in_range() {
+        let bright = FloatColor::new(0.25, 1.0, 8.0);
+
+        assert_eq!(
+            ToneMapping::Clamp.apply(bright),
+            FloatColor::new(0.25, 1.0, 1.0)
+        );
+        assert_eq!(
+            ToneMapping::Reinhard.apply(bright),
+            FloatColor::new(0.2, 0.5, 8.0 / 9.0)
+        );
+
+        // ACES keeps the order of the channels, rather than flattening the highlights
+        let aces = ToneMapping::Aces.apply(bright);
+        assert!(aces.r < aces.g && aces.g < aces.b && aces.b <= 1.0);
+        assert!(aces.b > 0.95);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let settings = HdrSettings {
+            tone_mapping: ToneMapping::Clamp,
+            exposure: -1.0,
+        };
+
+        assert_eq!(
+            settings.map(FloatColor::new(0.5, 1.0, 4.0)),
+            FloatColor::new(0.25, 0.5, 1.0)
+        );
+    }
+}
@@ -0,0 +1,54 @@
+pub mod attributes;
+pub mod camera;
+pub mod color;
+pub mod color_buffer;
+pub mod depth_buffer;
+mod drawing;
+pub mod export;
+mod fixed;
+pub mod fxaa;
+pub mod hdr;
+pub mod lighting;
+pub mod material;
+pub mod matrix;
+pub mod mesh;
+pub mod mtl;
+pub mod obj;
+mod plane;
+pub mod ply;
+mod polygon;
+pub mod post;
+pub mod render;
+pub mod scene;
+pub mod shader;
+pub mod shadow;
+mod simd;
+pub mod stl;
+pub mod texture;
+mod tile;
+pub mod triangle;
+pub mod vector;
+
+pub use attributes::Attributes;
+pub use camera::Camera;
+pub use color::{Color, FloatColor, LinearColor};
+pub use color_buffer::ColorBuffer;
+pub use depth_buffer::DepthBuffer;
+pub use drawing::PixelState;
+pub use fxaa::{Fxaa, FxaaQuality};
+pub use hdr::{HdrSettings, ToneMapping};
+pub use lighting::{Light, Lighting};
+pub use material::{BlendMode, Material};
+pub use matrix::Mat4;
+pub use mesh::{Mesh, MeshError};
+pub use post::{ColorGrading, Grayscale, Lut3d, PostEffect, Sharpen, Vignette};
+pub use render::{prepare_triangles, render, RenderMode, RenderSettings, ShadingMode};
+pub use scene::{Object, Scene};
+pub use shader::{
+    prepare_shaded_triangles, render_shaded, Fragment, FragmentShader, ShadedTriangle, Varyings,
+    VertexInput, VertexOutput, VertexShader,
+};
+pub use shadow::{render_shadow_maps, ShadowMap, ShadowSettings};
+pub use texture::{Filter, Sampler, Texture, WrapMode};
+pub use triangle::Triangle;
+pub use vector::Vec3;
@@ -0,0 +1,376 @@
+use serde::Deserialize;
+
+use crate::color::{Color, FloatColor, LinearColor};
+use crate::material::Material;
+use crate::shadow::{ShadowMap, ShadowSettings};
+use crate::texture::Texture;
+use crate::vector::Vec3;
+
+/// How a point or spot light's intensity falls off with distance:
+/// 1 / (constant + linear * distance + quadratic * distance^2)
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Self {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance)
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Self::new(1.0, 0.09, 0.032)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away light shining in one direction, like the sun
+    Directional { direction: Vec3 },
+    /// Light shining in all directions from a position
+    Point {
+        position: Vec3,
+        attenuation: Attenuation,
+    },
+    /// Light shining in a cone from a position. The cone angles are stored as cosines of the
+    /// half angles; the light fades out between the inner and outer cone.
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        inner_cos: f32,
+        outer_cos: f32,
+        attenuation: Attenuation,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: FloatColor,
+    pub intensity: f32,
+    /// Whether objects block this light. Only directional and spot lights cast shadows, so
+    /// point lights are created without them.
+    pub casts_shadows: bool,
+}
+
+impl Light {
+    pub fn directional(direction: Vec3, color: FloatColor, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional {
+                direction: direction.normalized(),
+            },
+            color,
+            intensity,
+            casts_shadows: true,
+        }
+    }
+
+    pub fn point(
+        position: Vec3,
+        attenuation: Attenuation,
+        color: FloatColor,
+        intensity: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Point {
+                position,
+                attenuation,
+            },
+            color,
+            intensity,
+            casts_shadows: false,
+        }
+    }
+
+    /// Creates a spot light. The inner and outer angles are the half angles of the cone in radians.
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+        attenuation: Attenuation,
+        color: FloatColor,
+        intensity: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                position,
+                direction: direction.normalized(),
+                inner_cos: inner_angle.cos(),
+                outer_cos: outer_angle.max(inner_angle).cos(),
+                attenuation,
+            },
+            color,
+            intensity,
+            casts_shadows: true,
+        }
+    }
+
+    /// Returns the direction from a point towards the light, and the light arriving at the point
+    pub fn incident(&self, position: Vec3) -> (Vec3, FloatColor) {
+        let radiance = self.color * self.intensity;
+
+        match self.kind {
+            LightKind::Directional { direction } => (direction * -1.0, radiance),
+            LightKind::Point {
+                position: light_position,
+                attenuation,
+            } => {
+                let to_light = light_position - position;
+                let distance = to_light.magnitude();
+
+                (
+                    to_light.normalized_or_zero(),
+                    radiance * attenuation.factor(distance),
+                )
+            }
+            LightKind::Spot {
+                position: light_position,
+                direction,
+                inner_cos,
+                outer_cos,
+                attenuation,
+            } => {
+                let to_light = light_position - position;
+                let distance = to_light.magnitude();
+                let to_light = to_light.normalized_or_zero();
+
+                // Fade linearly between the inner and outer cone
+                let cos_angle = (to_light * -1.0).dot(direction);
+                let cone = if inner_cos > outer_cos {
+                    ((cos_angle - outer_cos) / (inner_cos - outer_cos)).clamp(0.0, 1.0)
+                } else if cos_angle >= outer_cos {
+                    1.0
+                } else {
+                    0.0
+                };
+
+                (to_light, radiance * attenuation.factor(distance) * cone)
+            }
+        }
+    }
+}
+
+/// Scene state needed to light a point on a surface
+#[derive(Clone, Copy, Debug)]
+pub struct Lighting<'a> {
+    pub lights: &'a [Light],
+    /// Light added to every surface regardless of its orientation
+    pub ambient: FloatColor,
+    pub camera_position: Vec3,
+    /// Shadow map for each light, in the same order as the lights. Lights without a map are
+    /// never blocked.
+    pub shadow_maps: &'a [Option<ShadowMap>],
+    pub shadow_settings: ShadowSettings,
+}
+
+impl<'a> Lighting<'a> {
+    pub fn new(lights: &'a [Light], ambient: FloatColor, camera_position: Vec3) -> Self {
+        Self {
+            lights,
+            ambient,
+            camera_position,
+            shadow_maps: &[],
+            shadow_settings: ShadowSettings::default(),
+        }
+    }
+
+    pub fn with_shadows(
+        self,
+        shadow_maps: &'a [Option<ShadowMap>],
+        shadow_settings: ShadowSettings,
+    ) -> Self {
+        Self {
+            shadow_maps,
+            shadow_settings,
+            ..self
+        }
+    }
+
+    /// Light arriving at a point from one of the lights, blocked by its shadow map if it has one
+    fn incident(&self, index: usize, position: Vec3) -> (Vec3, FloatColor) {
+        let (to_light, radiance) = self.lights[index].incident(position);
+
+        match self.shadow_maps.get(index) {
+            Some(Some(shadow_map)) => (
+                to_light,
+                radiance * shadow_map.visibility(position, &self.shadow_settings),
+            ),
+            _ => (to_light, radiance),
+        }
+    }
+
+    /// Ambient plus Lambertian diffuse light arriving at a point on a surface
+    pub fn diffuse(&self, normal: Vec3, position: Vec3) -> FloatColor {
+        let mut diffuse = self.ambient;
+
+        for index in 0..self.lights.len() {
+            let (to_light, radiance) = self.incident(index, position);
+            diffuse += radiance * normal.dot(to_light).max(0.0);
+        }
+
+        diffuse
+    }
+
+    /// Blinn-Phong specular light reflected from a point on a surface towards the camera
+    pub fn specular(&self, normal: Vec3, position: Vec3, material: &Material) -> FloatColor {
+        let mut specular = FloatColor::BLACK;
+        let to_camera = (self.camera_position - position).normalized_or_zero();
+
+        for index in 0..self.lights.len() {
+            let (to_light, radiance) = self.incident(index, position);
+
+            if normal.dot(to_light) <= 0.0 {
+                continue;
+            }
+
+            let halfway = (to_light + to_camera).normalized_or_zero();
+
+            specular += radiance * normal.dot(halfway).max(0.0).powf(material.shininess);
+        }
+
+        specular * material.specular
+    }
+}
+
+/// Lighting evaluated per pixel for a single object
+#[derive(Clone, Copy)]
+pub struct PixelLighting<'a> {
+    pub lighting: &'a Lighting<'a>,
+    pub material: &'a Material,
+    /// Tangent space normal map bending the surface's normals
+    pub normal_map: Option<&'a Texture>,
+    /// Flip the normal map's texture coordinates vertically, matching the object's texture
+    pub flip_v: bool,
+}
+
+impl PixelLighting<'_> {
+    /// Light a surface color at a point, adding specular highlights on top
+    pub fn shade(&self, color: Color, normal: Vec3, position: Vec3) -> Color {
+        let diffuse = self.lighting.diffuse(normal, position);
+        let specular = self.lighting.specular(normal, position, self.material);
+
+        color * diffuse + Color::from(specular)
+    }
+
+    /// Light a surface color in linear space, adding specular highlights on top
+    pub fn shade_linear(&self, color: LinearColor, normal: Vec3, position: Vec3) -> LinearColor {
+        let diffuse = self.lighting.diffuse(normal, position);
+        let specular = self.lighting.specular(normal, position, self.material);
+
+        color * diffuse + specular
+    }
+}
+
+/// Bends a surface normal by a texel of a tangent space normal map, whose red, green and blue
+/// channels hold how far the normal leans along the tangent, bitangent and normal. The
+/// tangent and bitangent are made perpendicular to the normal first, since interpolating
+/// them across a face pulls them apart.
+pub fn perturb_normal(normal: Vec3, tangent: Vec3, bitangent: Vec3, texel: Color) -> Vec3 {
+    let tangent = (tangent - normal * normal.dot(tangent)).normalized_or_zero();
+    let bitangent = (bitangent - normal * normal.dot(bitangent) - tangent * tangent.dot(bitangent))
+        .normalized_or_zero();
+
+    let [x, y, z] = [texel.r, texel.g, texel.b].map(|channel| channel as f32 / 127.5 - 1.0);
+
+    (tangent * x + bitangent * y + normal * z).normalized_or_zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eq_f32(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.0001
+    }
+
+    #[test]
+    fn specular_peaks_when_facing_light_and_camera() {
+        let lights = [Light::directional(
+            Vec3::new(0.0, 0.0, 1.0),
+            FloatColor::WHITE,
+            1.0,
+        )];
+        let lighting = Lighting::new(&lights, FloatColor::BLACK, Vec3::new(0.0, 0.0, -5.0));
+        let material = Material::default();
+        let normal = Vec3::new(0.0, 0.0, -1.0);
+
+        let specular = lighting.specular(normal, Vec3::default(), &material);
+
+        assert!(eq_f32(specular.r, material.specular));
+    }
+
+    #[test]
+    fn no_specular_when_facing_away_from_light() {
+        let lights = [Light::directional(
+            Vec3::new(0.0, 0.0, 1.0),
+            FloatColor::WHITE,
+            1.0,
+        )];
+        let lighting = Lighting::new(&lights, FloatColor::BLACK, Vec3::new(0.0, 0.0, 5.0));
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+
+        let specular = lighting.specular(normal, Vec3::default(), &Material::default());
+
+        assert_eq!(specular, FloatColor::BLACK);
+    }
+
+    #[test]
+    fn normal_map_texels_lean_along_tangents() {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        // Interpolated tangents are rarely perpendicular to the normal
+        let tangent = Vec3::new(1.0, 0.0, 0.5);
+        let bitangent = Vec3::new(0.0, 2.0, 0.0);
+
+        let flat = perturb_normal(normal, tangent, bitangent, Color::new(128, 128, 255));
+        let leaning = perturb_normal(normal, tangent, bitangent, Color::new(218, 128, 218));
+
+        assert!((flat - normal).magnitude() < 0.01);
+        assert!((leaning - Vec3::new(1.0, 0.0, 1.0).normalized()).magnitude() < 0.01);
+    }
+
+    #[test]
+    fn point_light_attenuates_with_distance() {
+        let attenuation = Attenuation::new(1.0, 0.0, 1.0);
+        let light = Light::point(Vec3::default(), attenuation, FloatColor::WHITE, 1.0);
+
+        let (to_light, radiance) = light.incident(Vec3::new(2.0, 0.0, 0.0));
+
+        assert_eq!(to_light, Vec3::new(-1.0, 0.0, 0.0));
+        assert!(eq_f32(radiance.r, 0.2));
+    }
+
+    #[test]
+    fn spot_light_fades_between_cones() {
+        let light = Light::spot(
+            Vec3::default(),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+            std::f32::consts::FRAC_PI_2,
+            Attenuation::new(1.0, 0.0, 0.0),
+            FloatColor::WHITE,
+            1.0,
+        );
+
+        let (_, center) = light.incident(Vec3::new(0.0, 0.0, 1.0));
+        let (_, edge) = light.incident(Vec3::new(1.0, 0.0, 1.0));
+        let (_, behind) = light.incident(Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(eq_f32(center.r, 1.0));
+        assert!(eq_f32(edge.r, std::f32::consts::FRAC_1_SQRT_2));
+        assert!(eq_f32(behind.r, 0.0));
+    }
+}
@@ -1,75 +1,171 @@
-use std::path::Path;
+use std::env;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use std::time::Duration;
-use std::{env, time::Instant};
+#[cfg(feature = "window")]
+use std::time::{Duration, Instant};
 
-use camera::Camera;
-use color::Color;
-use depth_buffer::DepthBuffer;
-use matrix::Mat4;
+#[cfg(feature = "window")]
 use minifb::{Key, KeyRepeat, Window, WindowOptions};
 
-mod camera;
-mod color;
-mod color_buffer;
-mod depth_buffer;
-mod drawing;
-mod fixed;
-mod matrix;
-mod mesh;
-mod obj;
-mod plane;
-mod polygon;
-mod render;
-mod scene;
-mod texture;
-mod triangle;
-mod vector;
-
-use color_buffer::ColorBuffer;
-use mesh::Mesh;
-use render::{prepare_triangles, render, RenderMode, RenderSettings};
-use scene::reader::read_objects_from_scene;
-use scene::{Object, Scene};
-use texture::Texture;
-use triangle::Triangle;
-use vector::Vec3;
+use software_renderer::export::export_scene;
+use software_renderer::scene::gltf::read_gltf;
+use software_renderer::scene::reader::read_scene;
+#[cfg(feature = "window")]
+use software_renderer::ShadingMode;
+use software_renderer::{
+    render_shadow_maps, Camera, Color, ColorBuffer, DepthBuffer, Fxaa, FxaaQuality, Mat4, Material,
+    Mesh, Object, PostEffect, RenderMode, RenderSettings, Scene, Texture, ToneMapping, Triangle,
+    Vec3,
+};
 
 const RENDER_WIDTH: u16 = 512;
 const RENDER_HEIGHT: u16 = 384;
+#[cfg(feature = "window")]
 const WINDOW_WIDTH: usize = 1024;
+#[cfg(feature = "window")]
 const WINDOW_HEIGHT: usize = 768;
 
 const FRAME_RATE: f32 = 60.0;
 
-fn main() -> ExitCode {
-    let args: Vec<String> = env::args().collect();
+const HEADLESS_FRAME_TIME: f32 = 1.0 / FRAME_RATE;
+
+/// Stops of exposure added or removed by each key press
+#[cfg(feature = "window")]
+const EXPOSURE_STEP: f32 = 0.5;
+
+struct HeadlessOptions {
+    output_path: PathBuf,
+    frames: u32,
+}
+
+struct ExportOptions {
+    output_path: PathBuf,
+    /// Only export what's inside the camera's view frustum
+    frustum: bool,
+}
+
+struct Options {
+    input_path: PathBuf,
+    headless: Option<HeadlessOptions>,
+    export: Option<ExportOptions>,
+    threads: Option<usize>,
+    /// Light and blend the 8-bit sRGB colors directly, instead of in linear space
+    no_linear_lighting: bool,
+    tone_mapping: Option<ToneMapping>,
+    /// Exposure in stops
+    exposure: Option<f32>,
+    /// Skip the scene's post effects
+    no_post_process: bool,
+    fxaa: Option<FxaaQuality>,
+}
+
+fn print_usage() {
+    println!(
+        "Usage: software-renderer [--headless <output.png>] [--frames <count>] [--threads <count>] [--no-linear-lighting] [--tone-mapping <clamp|reinhard|aces>] [--exposure <stops>] [--no-post-process] [--fxaa <low|medium|high>] [--export <output.obj|output.ply>] [--export-frustum] [mesh or scene]"
+    );
+}
 
-    if args.len() != 2 {
-        eprintln!("Error: Incorrect arguments specified!");
-        println!("Usage: software-renderer [mesh or scene]");
-        return ExitCode::from(1);
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut input_path = None;
+    let mut output_path = None;
+    let mut frames = 1;
+    let mut threads = None;
+    let mut export_path = None;
+    let mut export_frustum = false;
+    let mut no_linear_lighting = false;
+    let mut tone_mapping = None;
+    let mut exposure = None;
+    let mut no_post_process = false;
+    let mut fxaa = None;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => {
+                let path = args.next().ok_or("--headless requires an output path")?;
+                output_path = Some(PathBuf::from(path));
+            }
+            "--frames" => {
+                frames = args
+                    .next()
+                    .and_then(|count| count.parse::<u32>().ok())
+                    .filter(|&count| count > 0)
+                    .ok_or("--frames requires a positive frame count")?;
+            }
+            "--threads" => {
+                threads = Some(
+                    args.next()
+                        .and_then(|count| count.parse::<usize>().ok())
+                        .filter(|&count| count > 0)
+                        .ok_or("--threads requires a positive thread count")?,
+                );
+            }
+            "--export" => {
+                let path = args.next().ok_or("--export requires an output path")?;
+                export_path = Some(PathBuf::from(path));
+            }
+            "--export-frustum" => export_frustum = true,
+            "--no-linear-lighting" => no_linear_lighting = true,
+            "--no-post-process" => no_post_process = true,
+            "--fxaa" => {
+                fxaa = Some(match args.next().map(String::as_str) {
+                    Some("low") => FxaaQuality::Low,
+                    Some("medium") => FxaaQuality::Medium,
+                    Some("high") => FxaaQuality::High,
+                    _ => return Err("--fxaa requires low, medium or high".to_string()),
+                });
+            }
+            "--tone-mapping" => {
+                tone_mapping = Some(match args.next().map(String::as_str) {
+                    Some("clamp") => ToneMapping::Clamp,
+                    Some("reinhard") => ToneMapping::Reinhard,
+                    Some("aces") => ToneMapping::Aces,
+                    _ => return Err("--tone-mapping requires clamp, reinhard or aces".to_string()),
+                });
+            }
+            "--exposure" => {
+                exposure = Some(
+                    args.next()
+                        .and_then(|stops| stops.parse::<f32>().ok())
+                        .filter(|stops| stops.is_finite())
+                        .ok_or("--exposure requires a number of stops")?,
+                );
+            }
+            _ if input_path.is_none() => input_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
     }
 
-    // Window setup
-    let mut color_buffer = ColorBuffer::new(RENDER_WIDTH, RENDER_HEIGHT);
-    let mut depth_buffer = DepthBuffer::new(RENDER_WIDTH, RENDER_HEIGHT);
+    let input_path = input_path.ok_or("no mesh or scene specified")?;
 
-    let mut window = Window::new(
-        "3D Renderer",
-        WINDOW_WIDTH,
-        WINDOW_HEIGHT,
-        WindowOptions {
-            resize: true,
-            scale_mode: minifb::ScaleMode::AspectRatioStretch,
-            ..Default::default()
-        },
-    )
-    .expect("Error: Window could not be created!");
+    if output_path.is_none() && frames != 1 {
+        return Err("--frames can only be used with --headless".to_string());
+    }
 
-    window.limit_update_rate(Some(Duration::from_secs_f32(1.0 / FRAME_RATE)));
+    if export_path.is_none() && export_frustum {
+        return Err("--export-frustum can only be used with --export".to_string());
+    }
 
-    // Scene setup
+    Ok(Options {
+        input_path,
+        headless: output_path.map(|output_path| HeadlessOptions {
+            output_path,
+            frames,
+        }),
+        export: export_path.map(|output_path| ExportOptions {
+            output_path,
+            frustum: export_frustum,
+        }),
+        threads,
+        no_linear_lighting,
+        tone_mapping,
+        exposure,
+        no_post_process,
+        fxaa,
+    })
+}
+
+fn load_scene(path: &Path) -> Result<Scene, String> {
     let camera = Camera::new(
         Vec3::new(0.0, 0.0, -5.0),
         Vec3::new(0.0, 1.0, 0.0),
@@ -77,63 +173,167 @@ fn main() -> ExitCode {
         0.0,
     );
 
-    let mut scene = Scene::new(camera);
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
 
-    if args[1].ends_with(".obj") {
-        // An OBJ file was specified
-        let mesh_path = Path::new(&args[1]);
-        let mesh = Mesh::from_obj(
-            mesh_path,
-            Vec3::default(),
-            Vec3::splat(1.0),
-            Vec3::default(),
-        );
+    match extension.as_deref() {
+        Some("obj" | "stl" | "ply") => {
+            let mesh = Mesh::from_file(path, Vec3::default(), Vec3::splat(1.0), Vec3::default())
+                .map_err(|e| format!("Error reading mesh file: {e}"))?;
 
-        let texture_path = Path::new(&args[1]).with_extension("png");
-        let texture = Texture::from_png(&texture_path).unwrap_or_else(|err| {
-            eprintln!("Error reading texture: {err}");
-            Texture::from_color(1, 1, Color::new(0xFF, 0x00, 0xFF))
-        });
+            for warning in mesh.warnings.iter() {
+                eprintln!("Warning: {warning}");
+            }
 
-        scene.add_object(Object { mesh, texture });
-    } else {
+            // OBJ files may have a texture next to them, unless their materials have their own.
+            // STL and PLY files have no textures, so are drawn in white or their vertex colors.
+            let texture = if extension.as_deref() == Some("obj") {
+                let texture_path = path.with_extension("png");
+                Texture::from_png(&texture_path).unwrap_or_else(|err| {
+                    if mesh.materials.is_empty() {
+                        eprintln!("Error reading texture: {err}");
+                    }
+                    Texture::from_color(1, 1, Color::new(0xFF, 0x00, 0xFF))
+                })
+            } else {
+                Texture::from_color(1, 1, Color::new(0xFF, 0xFF, 0xFF))
+            };
+
+            let mut scene = Scene::new(camera);
+            scene.add_object(Object::new(mesh, texture, Material::default()));
+
+            Ok(scene)
+        }
+        Some("gltf" | "glb") => {
+            read_gltf(path, camera).map_err(|e| format!("Error reading glTF file: {e}"))
+        }
         // Assume a scene file was specified
-        let scene_path = Path::new(&args[1]);
-        let objects = match read_objects_from_scene(scene_path) {
-            Ok(objects) => objects,
-            Err(e) => {
-                eprintln!("Error reading scene file: {e}");
-                return ExitCode::from(1);
-            }
-        };
+        _ => read_scene(path, camera).map_err(|e| format!("Error reading scene file: {e}")),
+    }
+}
 
-        for object in objects {
-            scene.add_object(object);
+fn render_frame(
+    scene: &Scene,
+    color_buffer: &mut ColorBuffer,
+    depth_buffer: &mut DepthBuffer,
+    triangles_to_render: &mut Vec<Triangle>,
+    projection_matrix: Mat4,
+    render_settings: &RenderSettings,
+) {
+    color_buffer.draw_grid();
+
+    let shadow_maps = render_shadow_maps(scene, &render_settings.shadows);
+    let lighting = scene
+        .lighting()
+        .with_shadows(&shadow_maps, render_settings.shadows);
+
+    scene.render(
+        color_buffer,
+        depth_buffer,
+        triangles_to_render,
+        projection_matrix,
+        render_settings,
+        &lighting,
+    );
+
+    // Depth views show depth values directly, so they aren't tone mapped
+    if render_settings.render_mode != RenderMode::Depth {
+        color_buffer.resolve_hdr(&render_settings.hdr);
+
+        if let Some(quality) = render_settings.fxaa {
+            Fxaa { quality }.apply(color_buffer, depth_buffer);
+        }
+        if render_settings.post_processing {
+            scene.post_process(color_buffer, depth_buffer);
         }
     }
+}
 
-    // Main loop preparation
+/// Returns the path a headless frame is written to. Single frame renders use the
+/// output path as-is, while sequences get the frame number appended to the file stem.
+fn frame_output_path(output_path: &Path, frame: u32, frames: u32) -> PathBuf {
+    if frames == 1 {
+        return output_path.to_path_buf();
+    }
+
+    let stem = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let extension = output_path
+        .extension()
+        .map(|extension| extension.to_string_lossy())
+        .unwrap_or("png".into());
+
+    output_path.with_file_name(format!("{stem}_{frame:04}.{extension}"))
+}
+
+fn run_headless(
+    mut scene: Scene,
+    options: &HeadlessOptions,
+    projection_matrix: Mat4,
+    render_settings: &RenderSettings,
+) -> ExitCode {
+    let mut color_buffer = ColorBuffer::new_hdr(RENDER_WIDTH, RENDER_HEIGHT);
+    let mut depth_buffer = DepthBuffer::new(RENDER_WIDTH, RENDER_HEIGHT);
     let mut triangles_to_render: Vec<Triangle> = Vec::new();
 
-    let projection_matrix = Mat4::projection(
-        std::f32::consts::FRAC_PI_2,
-        RENDER_HEIGHT as f32 / RENDER_WIDTH as f32,
-        0.1,
-        100.0,
-    );
+    for frame in 0..options.frames {
+        // Frames are spaced at the interactive frame rate so animations match the viewer
+        scene.animate(render_settings, frame as f32 * HEADLESS_FRAME_TIME);
+
+        render_frame(
+            &scene,
+            &mut color_buffer,
+            &mut depth_buffer,
+            &mut triangles_to_render,
+            projection_matrix,
+            render_settings,
+        );
 
-    let mut render_settings = RenderSettings {
-        render_mode: RenderMode::Textured,
-        backface_cull: true,
-        shaded: true,
-        translate: false,
-        rotate: true,
-        rotation: Vec3::new(0.0, 0.01, 0.0),
-        scale: false,
-        flip_uvs_vertically: false,
-        render_width: RENDER_WIDTH,
-        render_height: RENDER_HEIGHT,
-    };
+        let frame_path = frame_output_path(&options.output_path, frame, options.frames);
+        if let Err(e) = color_buffer.write_png(&frame_path) {
+            eprintln!("Error writing frame: {e}");
+            return ExitCode::from(1);
+        }
+
+        println!("Wrote {}", frame_path.display());
+
+        depth_buffer.clear(1.0);
+        color_buffer.clear(Color::new(0, 0, 0));
+    }
+
+    ExitCode::from(0)
+}
+
+#[cfg(feature = "window")]
+fn run_viewer(
+    mut scene: Scene,
+    projection_matrix: Mat4,
+    mut render_settings: RenderSettings,
+) -> ExitCode {
+    // Window setup
+    let mut color_buffer = ColorBuffer::new_hdr(RENDER_WIDTH, RENDER_HEIGHT);
+    let mut depth_buffer = DepthBuffer::new(RENDER_WIDTH, RENDER_HEIGHT);
+
+    let mut window = Window::new(
+        "3D Renderer",
+        WINDOW_WIDTH,
+        WINDOW_HEIGHT,
+        WindowOptions {
+            resize: true,
+            scale_mode: minifb::ScaleMode::AspectRatioStretch,
+            ..Default::default()
+        },
+    )
+    .expect("Error: Window could not be created!");
+
+    window.limit_update_rate(Some(Duration::from_secs_f32(1.0 / FRAME_RATE)));
+
+    // Main loop preparation
+    let mut triangles_to_render: Vec<Triangle> = Vec::new();
 
     let start_time = Instant::now();
     let mut last_frame_time = start_time;
@@ -181,6 +381,98 @@ fn main() -> ExitCode {
             println!("Lighting disabled");
         }
 
+        if window.is_key_pressed(Key::H, KeyRepeat::No) {
+            render_settings.shading_mode = match render_settings.shading_mode {
+                ShadingMode::Flat => {
+                    println!("Gouraud shading enabled");
+                    ShadingMode::Gouraud
+                }
+                ShadingMode::Gouraud => {
+                    println!("Phong shading enabled");
+                    ShadingMode::Phong
+                }
+                ShadingMode::Phong => {
+                    println!("Flat shading enabled");
+                    ShadingMode::Flat
+                }
+            };
+        }
+
+        if window.is_key_pressed(Key::O, KeyRepeat::No) {
+            render_settings.shadows.enabled = !render_settings.shadows.enabled;
+
+            if render_settings.shadows.enabled {
+                println!("Shadows enabled");
+            } else {
+                println!("Shadows disabled");
+            }
+        }
+
+        if window.is_key_pressed(Key::K, KeyRepeat::No) {
+            render_settings.shadows.pcf_radius = if render_settings.shadows.pcf_radius == 0 {
+                println!("Soft shadows enabled");
+                1
+            } else {
+                println!("Soft shadows disabled");
+                0
+            };
+        }
+
+        if window.is_key_pressed(Key::V, KeyRepeat::No) {
+            render_settings.linear_lighting = !render_settings.linear_lighting;
+
+            if render_settings.linear_lighting {
+                println!("Linear lighting enabled");
+            } else {
+                println!("Linear lighting disabled");
+            }
+        }
+
+        if window.is_key_pressed(Key::E, KeyRepeat::No) {
+            render_settings.hdr.tone_mapping = render_settings.hdr.tone_mapping.next();
+            println!("{} tone mapping enabled", render_settings.hdr.tone_mapping);
+        }
+
+        if window.is_key_pressed(Key::LeftBracket, KeyRepeat::No) {
+            render_settings.hdr.exposure -= EXPOSURE_STEP;
+            println!("Exposure set to {:+} stops", render_settings.hdr.exposure);
+        } else if window.is_key_pressed(Key::RightBracket, KeyRepeat::No) {
+            render_settings.hdr.exposure += EXPOSURE_STEP;
+            println!("Exposure set to {:+} stops", render_settings.hdr.exposure);
+        }
+
+        if window.is_key_pressed(Key::N, KeyRepeat::No) {
+            render_settings.fxaa = match render_settings.fxaa {
+                None => Some(FxaaQuality::Low),
+                Some(quality) => quality.next(),
+            };
+
+            match render_settings.fxaa {
+                Some(quality) => println!("FXAA enabled at {quality} quality"),
+                None => println!("FXAA disabled"),
+            }
+        }
+
+        if window.is_key_pressed(Key::B, KeyRepeat::No) {
+            render_settings.post_processing = !render_settings.post_processing;
+
+            if render_settings.post_processing {
+                println!("Post processing enabled");
+            } else {
+                println!("Post processing disabled");
+            }
+        }
+
+        if window.is_key_pressed(Key::M, KeyRepeat::No) {
+            render_settings.mipmapping = !render_settings.mipmapping;
+
+            if render_settings.mipmapping {
+                println!("Mipmapping enabled");
+            } else {
+                println!("Mipmapping disabled");
+            }
+        }
+
         if window.is_key_pressed(Key::T, KeyRepeat::No) {
             render_settings.translate = !render_settings.translate;
 
@@ -252,25 +544,14 @@ fn main() -> ExitCode {
             delta_time,
         );
 
-        color_buffer.draw_grid();
-
-        for object in scene.objects() {
-            prepare_triangles(
-                &mut triangles_to_render,
-                projection_matrix,
-                &object.mesh,
-                &scene.camera,
-                &render_settings,
-            );
-
-            render(
-                &mut color_buffer,
-                &mut depth_buffer,
-                &triangles_to_render,
-                &render_settings,
-                &object.texture,
-            );
-        }
+        render_frame(
+            &scene,
+            &mut color_buffer,
+            &mut depth_buffer,
+            &mut triangles_to_render,
+            projection_matrix,
+            &render_settings,
+        );
 
         window
             .update_with_buffer(
@@ -284,5 +565,84 @@ fn main() -> ExitCode {
         color_buffer.clear(Color::new(0, 0, 0));
     }
 
-    return ExitCode::from(0);
+    ExitCode::from(0)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            print_usage();
+            return ExitCode::from(1);
+        }
+    };
+
+    // Scene setup
+    let scene = match load_scene(&options.input_path) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(1);
+        }
+    };
+
+    let projection_matrix = Mat4::projection(
+        std::f32::consts::FRAC_PI_2,
+        RENDER_HEIGHT as f32 / RENDER_WIDTH as f32,
+        0.1,
+        100.0,
+    );
+
+    // Exporting writes the scene's geometry instead of opening the viewer, though a headless
+    // render is still written if one was asked for too
+    if let Some(export_options) = &options.export {
+        let frustum = export_options.frustum.then_some(projection_matrix);
+
+        match export_scene(&scene, &export_options.output_path, frustum) {
+            Ok(()) => println!("Wrote {}", export_options.output_path.display()),
+            Err(e) => {
+                eprintln!("Error exporting scene: {e}");
+                return ExitCode::from(1);
+            }
+        }
+
+        if options.headless.is_none() {
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    let mut render_settings = RenderSettings::new(RENDER_WIDTH, RENDER_HEIGHT);
+
+    if let Some(threads) = options.threads {
+        render_settings.threads = threads;
+    }
+
+    render_settings.linear_lighting = !options.no_linear_lighting;
+    render_settings.post_processing = !options.no_post_process;
+    render_settings.fxaa = options.fxaa;
+
+    if let Some(tone_mapping) = options.tone_mapping {
+        render_settings.hdr.tone_mapping = tone_mapping;
+    }
+    if let Some(exposure) = options.exposure {
+        render_settings.hdr.exposure = exposure;
+    }
+
+    match &options.headless {
+        Some(headless_options) => {
+            run_headless(scene, headless_options, projection_matrix, &render_settings)
+        }
+        #[cfg(feature = "window")]
+        None => run_viewer(scene, projection_matrix, render_settings),
+        #[cfg(not(feature = "window"))]
+        None => {
+            eprintln!(
+                "Error: the viewer requires the window feature, use --headless or --export instead"
+            );
+            ExitCode::from(1)
+        }
+    }
 }
@@ -0,0 +1,52 @@
+use serde::Deserialize;
+
+/// How a surface's pixels are combined with the pixels already drawn behind it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// Replace the pixels behind, ignoring alpha
+    #[default]
+    Opaque,
+    /// Composite over the pixels behind by alpha (source-over)
+    SourceOver,
+    /// Add the alpha weighted color to the pixels behind
+    Additive,
+    /// Tint the pixels behind by the alpha weighted color
+    Multiply,
+}
+
+/// Surface properties used when lighting an object
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    /// Strength of specular highlights, from 0 (matte) to 1
+    pub specular: f32,
+    /// Blinn-Phong exponent, higher values give smaller and sharper highlights
+    pub shininess: f32,
+    pub blend_mode: BlendMode,
+    /// Multiplies the alpha of the object's surface color, from 0 (invisible) to 1
+    pub opacity: f32,
+    /// Pixels with an alpha below this are discarded, which cuts out shapes such as leaves
+    /// from a texture. 0 keeps every pixel.
+    pub alpha_cutoff: f32,
+}
+
+impl Material {
+    /// Whether the material's surfaces are blended with what's behind them, and so need to
+    /// be drawn after opaque surfaces
+    pub fn is_transparent(&self) -> bool {
+        self.blend_mode != BlendMode::Opaque
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            specular: 0.5,
+            shininess: 32.0,
+            blend_mode: BlendMode::Opaque,
+            opacity: 1.0,
+            alpha_cutoff: 0.0,
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::ops::{Mul, MulAssign};
 
-use crate::vector::Vec4;
+use crate::vector::{Vec3, Vec4};
 
 /// Column major format
 #[derive(Clone, Copy, Debug)]
@@ -85,6 +85,42 @@ impl Mat4 {
         )
     }
 
+    /// Orthographic projection mapping the given box to x and y in [-1, 1] and z in [0, 1]
+    pub fn orthographic(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        znear: f32,
+        zfar: f32,
+    ) -> Self {
+        Self::new(
+            Vec4::new(2.0 / (right - left), 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 2.0 / (top - bottom), 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 1.0 / (zfar - znear), 0.0),
+            Vec4::new(
+                -(right + left) / (right - left),
+                -(top + bottom) / (top - bottom),
+                -znear / (zfar - znear),
+                1.0,
+            ),
+        )
+    }
+
+    /// View matrix for an eye at a position looking in a direction
+    pub fn look_to(eye: Vec3, direction: Vec3, up: Vec3) -> Self {
+        let z = direction.normalized();
+        let x = up.cross(z).normalized();
+        let y = z.cross(x).normalized();
+
+        Self::new(
+            Vec4::new(x.x, y.x, z.x, 0.0),
+            Vec4::new(x.y, y.y, z.y, 0.0),
+            Vec4::new(x.z, y.z, z.z, 0.0),
+            Vec4::new(-x.dot(eye), -y.dot(eye), -z.dot(eye), 1.0),
+        )
+    }
+
     pub fn project_vec4(&self, vector: Vec4) -> Vec4 {
         let mut multiplied = self * vector;
 
@@ -165,7 +201,7 @@ impl MulAssign<Mat4> for Mat4 {
 
 #[cfg(test)]
 mod tests {
-    use crate::vector::Vec4;
+    use crate::vector::{Vec3, Vec4};
 
     use super::Mat4;
 
@@ -220,4 +256,36 @@ mod tests {
             )
         ));
     }
+
+    #[test]
+    fn orthographic_maps_box_to_clip_space() {
+        let a = Mat4::orthographic(-2.0, 2.0, -1.0, 1.0, 1.0, 5.0);
+
+        assert!(eq_vec4(
+            a * Vec4::new(-2.0, -1.0, 1.0, 1.0),
+            Vec4::new(-1.0, -1.0, 0.0, 1.0)
+        ));
+        assert!(eq_vec4(
+            a * Vec4::new(2.0, 1.0, 5.0, 1.0),
+            Vec4::new(1.0, 1.0, 1.0, 1.0)
+        ));
+    }
+
+    #[test]
+    fn look_to_moves_eye_to_origin() {
+        let a = Mat4::look_to(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+
+        assert!(eq_vec4(
+            a * Vec4::new(1.0, 2.0, 3.0, 1.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0)
+        ));
+        assert!(eq_vec4(
+            a * Vec4::new(3.0, 2.0, 3.0, 1.0),
+            Vec4::new(0.0, 0.0, 2.0, 1.0)
+        ));
+    }
 }
@@ -1,11 +1,81 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::attributes::Attributes;
+use crate::color::Color;
+use crate::matrix::Mat4;
+use crate::mtl::MtlMaterial;
+use crate::obj::ObjError;
+use crate::ply::PlyError;
+use crate::stl::StlError;
 use crate::texture::Tex2;
 use crate::triangle::Face;
 use crate::vector::Vec3;
 
+/// Why a mesh file could not be read, for any of the supported formats
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MeshError<'a> {
+    Obj(ObjError<'a>),
+    Stl(StlError<'a>),
+    Ply(PlyError<'a>),
+    /// The file's extension isn't one of the supported formats
+    UnsupportedFormat(Cow<'a, str>),
+}
+
+impl MeshError<'_> {
+    pub fn into_owned(self) -> MeshError<'static> {
+        match self {
+            Self::Obj(err) => MeshError::Obj(err.into_owned()),
+            Self::Stl(err) => MeshError::Stl(err.into_owned()),
+            Self::Ply(err) => MeshError::Ply(err.into_owned()),
+            Self::UnsupportedFormat(path) => {
+                MeshError::UnsupportedFormat(Cow::Owned(path.into_owned()))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for MeshError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Obj(err) => write!(f, "{err}"),
+            Self::Stl(err) => write!(f, "{err}"),
+            Self::Ply(err) => write!(f, "{err}"),
+            Self::UnsupportedFormat(path) => {
+                write!(
+                    f,
+                    "{path}: unsupported mesh format, expected OBJ, STL or PLY"
+                )
+            }
+        }
+    }
+}
+
+/// Directions along a surface in which its texture coordinates change, used to turn the
+/// normals of a normal map into world space
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Tangent {
+    /// Direction in which u increases
+    pub tangent: Vec3,
+    /// Direction up the texture, in which v decreases
+    pub bitangent: Vec3,
+}
+
 pub struct Mesh {
     pub vertices: Vec<Vec3>,
     pub vertex_uvs: Vec<Tex2>,
+    pub vertex_normals: Vec<Vec3>,
+    /// Color of each vertex, or empty if the mesh has no vertex colors
+    pub vertex_colors: Vec<Color>,
+    /// Tangents faces can refer to, or empty until they are generated
+    pub vertex_tangents: Vec<Tangent>,
     pub faces: Vec<Face>,
+    /// Materials faces can refer to, read from the mesh's material libraries
+    pub materials: Vec<MtlMaterial>,
+    /// Problems found while reading the mesh that didn't stop it from being read, such as
+    /// missing material libraries, left for the caller to report
+    pub warnings: Vec<String>,
     pub rotation: Vec3,
     pub scale: Vec3,
     pub translation: Vec3,
@@ -16,6 +86,7 @@ impl Mesh {
     pub fn new(
         vertices: Vec<Vec3>,
         vertex_uvs: Vec<Tex2>,
+        vertex_normals: Vec<Vec3>,
         faces: Vec<Face>,
         rotation: Vec3,
         scale: Vec3,
@@ -24,7 +95,12 @@ impl Mesh {
         Self {
             vertices,
             vertex_uvs,
+            vertex_normals,
+            vertex_colors: Vec::new(),
+            vertex_tangents: Vec::new(),
             faces,
+            materials: Vec::new(),
+            warnings: Vec::new(),
             rotation,
             scale,
             translation,
@@ -32,7 +108,177 @@ impl Mesh {
         }
     }
 
+    /// Reads an OBJ, STL or PLY file, picking the format from the file's extension
+    pub fn from_file(
+        path: &Path,
+        rotation: Vec3,
+        scale: Vec3,
+        translation: Vec3,
+    ) -> Result<Self, MeshError<'_>> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_ref().and_then(|extension| extension.to_str()) {
+            Some("obj") => {
+                Self::from_obj(path, rotation, scale, translation).map_err(MeshError::Obj)
+            }
+            Some("stl") => {
+                Self::from_stl(path, rotation, scale, translation).map_err(MeshError::Stl)
+            }
+            Some("ply") => {
+                Self::from_ply(path, rotation, scale, translation).map_err(MeshError::Ply)
+            }
+            _ => Err(MeshError::UnsupportedFormat(path.to_string_lossy())),
+        }
+    }
+
+    pub fn with_vertex_colors(mut self, vertex_colors: Vec<Color>) -> Self {
+        self.vertex_colors = vertex_colors;
+        self
+    }
+
+    pub fn with_materials(mut self, materials: Vec<MtlMaterial>) -> Self {
+        self.materials = materials;
+        self
+    }
+
+    pub fn with_warnings(mut self, warnings: Vec<String>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Generates a tangent and bitangent for each corner of every face from its positions and
+    /// texture coordinates. Corners sharing both a position and a texture coordinate share a
+    /// tangent averaged over their faces, so seams in the texture keep their own.
+    pub fn generate_tangents(&mut self) {
+        let mut tangents: Vec<Tangent> = Vec::new();
+        let mut shared_tangents: HashMap<(u32, u32), u32> = HashMap::new();
+
+        for face in self.faces.iter_mut() {
+            let positions = [face.a, face.b, face.c].map(|i| self.vertices[i as usize]);
+            let uvs = [face.a_uv, face.b_uv, face.c_uv].map(|i| self.vertex_uvs[i as usize]);
+            let face_tangent = face_tangent(positions, uvs);
+
+            let corners = [
+                (face.a, face.a_uv),
+                (face.b, face.b_uv),
+                (face.c, face.c_uv),
+            ];
+            face.tangents = Some(corners.map(|corner| {
+                let index = *shared_tangents.entry(corner).or_insert_with(|| {
+                    tangents.push(Tangent::default());
+                    tangents.len() as u32 - 1
+                });
+
+                let tangent = &mut tangents[index as usize];
+                tangent.tangent += face_tangent.tangent;
+                tangent.bitangent += face_tangent.bitangent;
+
+                index
+            }));
+        }
+
+        for tangent in tangents.iter_mut() {
+            tangent.tangent = tangent.tangent.normalized_or_zero();
+            tangent.bitangent = tangent.bitangent.normalized_or_zero();
+        }
+
+        self.vertex_tangents = tangents;
+    }
+
+    /// Color of a vertex for the built-in pipeline, which is white for meshes without vertex
+    /// colors
+    pub fn vertex_color(&self, vertex_index: usize) -> Attributes<4> {
+        self.vertex_colors
+            .get(vertex_index)
+            .map_or(Attributes::splat(1.0), |&color| color.into())
+    }
+
     pub fn initial_translation(&self) -> Vec3 {
         self.initial_translation
     }
+
+    /// Transforms the mesh from model space into world space
+    pub fn world_matrix(&self) -> Mat4 {
+        Mat4::translation(self.translation.x, self.translation.y, self.translation.z)
+            * Mat4::rotation_x(self.rotation.x)
+            * Mat4::rotation_y(self.rotation.y)
+            * Mat4::rotation_z(self.rotation.z)
+            * Mat4::scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// Transforms normals from model space into world space. This is the inverse transpose of
+    /// the world matrix without its translation, so normals stay perpendicular under scaling.
+    pub fn normal_matrix(&self) -> Mat4 {
+        Mat4::rotation_x(self.rotation.x)
+            * Mat4::rotation_y(self.rotation.y)
+            * Mat4::rotation_z(self.rotation.z)
+            * Mat4::scale(1.0 / self.scale.x, 1.0 / self.scale.y, 1.0 / self.scale.z)
+    }
+}
+
+/// Tangent and bitangent of a face, found by solving for the directions that move one unit
+/// along u and v across it. Faces whose texture coordinates are all in a line have none.
+fn face_tangent(positions: [Vec3; 3], uvs: [Tex2; 3]) -> Tangent {
+    let edge_1 = positions[1] - positions[0];
+    let edge_2 = positions[2] - positions[0];
+
+    // Texture coordinates are stored with v pointing down the texture, while normal maps
+    // expect their bitangent to point up it
+    let (du_1, dv_1) = (uvs[1].u - uvs[0].u, uvs[0].v - uvs[1].v);
+    let (du_2, dv_2) = (uvs[2].u - uvs[0].u, uvs[0].v - uvs[2].v);
+
+    let determinant = du_1 * dv_2 - du_2 * dv_1;
+    if determinant.abs() < f32::EPSILON {
+        return Tangent::default();
+    }
+
+    Tangent {
+        tangent: (edge_1 * dv_2 - edge_2 * dv_1) / determinant,
+        bitangent: (edge_2 * du_1 - edge_1 * du_2) / determinant,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tangents_follow_texture_coordinates() {
+        // A quad facing +z, with its texture upright when seen from the front
+        let mut mesh = Mesh::new(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            vec![
+                Tex2::new(0.0, 1.0),
+                Tex2::new(1.0, 1.0),
+                Tex2::new(1.0, 0.0),
+                Tex2::new(0.0, 0.0),
+            ],
+            Vec::new(),
+            vec![
+                Face::new(0, 1, 2, 0, 1, 2, Color::new(255, 255, 255)),
+                Face::new(0, 2, 3, 0, 2, 3, Color::new(255, 255, 255)),
+            ],
+            Vec3::default(),
+            Vec3::splat(1.0),
+            Vec3::default(),
+        );
+
+        mesh.generate_tangents();
+
+        // The two corners on the quad's diagonal are shared by both faces
+        assert_eq!(mesh.vertex_tangents.len(), 4);
+        assert_eq!(mesh.faces[1].tangents, Some([0, 2, 3]));
+
+        for tangent in mesh.vertex_tangents.iter() {
+            assert!((tangent.tangent - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-5);
+            assert!((tangent.bitangent - Vec3::new(0.0, 1.0, 0.0)).magnitude() < 1e-5);
+        }
+    }
 }
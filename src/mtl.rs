@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::color::FloatColor;
+use crate::material::{BlendMode, Material};
+use crate::obj::{read_numbers, ObjError, ObjErrorReason};
+
+/// A material read from an MTL material library
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// Diffuse color (Kd), which also tints the diffuse texture
+    pub diffuse: FloatColor,
+    /// Specular color (Ks)
+    pub specular: FloatColor,
+    /// Specular exponent (Ns)
+    pub shininess: f32,
+    /// Dissolve (d, or 1 - Tr), from 0 (invisible) to 1
+    pub opacity: f32,
+    /// Illumination model (illum). Models 0 and 1 have no specular highlights.
+    pub illumination: u32,
+    /// Diffuse texture (map_Kd)
+    pub diffuse_map: Option<PathBuf>,
+    /// Bump or normal map (map_Bump or bump)
+    pub bump_map: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            diffuse: FloatColor::WHITE,
+            specular: FloatColor::BLACK,
+            shininess: Material::default().shininess,
+            opacity: 1.0,
+            illumination: 2,
+            diffuse_map: None,
+            bump_map: None,
+        }
+    }
+
+    /// The renderer's material for surfaces using this MTL material. Settings MTL files don't
+    /// have, such as the alpha cutoff, are taken from `base`.
+    pub fn to_material(&self, base: Material) -> Material {
+        let specular = if self.illumination < 2 {
+            0.0
+        } else {
+            (self.specular.r + self.specular.g + self.specular.b) / 3.0
+        };
+
+        // Dissolved materials are see-through even if the base material is opaque
+        let blend_mode = if self.opacity < 1.0 && base.blend_mode == BlendMode::Opaque {
+            BlendMode::SourceOver
+        } else {
+            base.blend_mode
+        };
+
+        Material {
+            specular,
+            shininess: self.shininess,
+            blend_mode,
+            opacity: self.opacity,
+            ..base
+        }
+    }
+}
+
+/// Reads the materials of an MTL material library. Texture paths are relative to the
+/// library's directory.
+pub fn read_mtl(mtl_file_path: &Path) -> Result<Vec<MtlMaterial>, ObjError<'_>> {
+    let mtl_file = File::open(mtl_file_path).map_err(|_| ObjError {
+        path: mtl_file_path.to_string_lossy(),
+        line: None,
+        reason: ObjErrorReason::OpenError,
+    })?;
+
+    let directory = mtl_file_path.parent().unwrap_or(Path::new(""));
+
+    read_mtl_lines(BufReader::new(mtl_file), directory).map_err(|(line, reason)| ObjError {
+        path: mtl_file_path.to_string_lossy(),
+        line: Some(line),
+        reason,
+    })
+}
+
+fn read_mtl_lines(
+    reader: impl BufRead,
+    directory: &Path,
+) -> Result<Vec<MtlMaterial>, (usize, ObjErrorReason)> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for (line_index, potential_line) in reader.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = potential_line.map_err(|_| (line_number, ObjErrorReason::ReadError))?;
+        let invalid = (line_number, ObjErrorReason::InvalidMaterial);
+
+        let Some(keyword) = line.split_ascii_whitespace().next() else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            let name = line[keyword.len()..].trim();
+            materials.push(MtlMaterial::new(name.to_string()));
+            continue;
+        }
+
+        // Every other statement sets a property of the last material declared
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" | "Ks" => {
+                let color = read_numbers::<3, 3>(&line).ok_or(invalid)?;
+                let color = FloatColor::new(color[0], color[1], color[2]);
+
+                if keyword == "Kd" {
+                    material.diffuse = color;
+                } else {
+                    material.specular = color;
+                }
+            }
+            "Ns" => material.shininess = read_numbers::<1, 1>(&line).ok_or(invalid)?[0],
+            "d" => material.opacity = read_numbers::<1, 1>(&line).ok_or(invalid)?[0],
+            "Tr" => material.opacity = 1.0 - read_numbers::<1, 1>(&line).ok_or(invalid)?[0],
+            "illum" => {
+                material.illumination = line
+                    .split_ascii_whitespace()
+                    .nth(1)
+                    .and_then(|illum| illum.parse().ok())
+                    .ok_or(invalid)?;
+            }
+            "map_Kd" => {
+                material.diffuse_map = Some(read_map_path(&line, directory).ok_or(invalid)?)
+            }
+            "map_Bump" | "map_bump" | "bump" => {
+                material.bump_map = Some(read_map_path(&line, directory).ok_or(invalid)?);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Reads the file name of a texture map statement, skipping any options before it
+fn read_map_path(line: &str, directory: &Path) -> Option<PathBuf> {
+    // A texture map line should look like this:
+    // map_Kd [-<option> <values>...] <file name>
+    let file_name = line.split_ascii_whitespace().skip(1).last()?;
+
+    // Libraries exported on Windows may separate directories with backslashes
+    Some(directory.join(file_name.replace('\\', "/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn materials_can_be_read() {
+        let materials = read_mtl_lines(
+            "# Two materials\n\
+             newmtl Body\n\
+             Kd 0.8 0.2 0.1\n\
+             Ks 0.5 0.5 0.5\n\
+             Ns 96.0\n\
+             illum 2\n\
+             map_Kd -bm 1.0 textures\\body.png\n\
+             map_Bump body_normal.png\n\
+             \n\
+             newmtl Glass\n\
+             Kd 0.9 0.9 1.0\n\
+             d 0.25\n\
+             illum 1\n"
+                .as_bytes(),
+            Path::new("assets"),
+        )
+        .unwrap();
+
+        assert_eq!(materials.len(), 2);
+
+        let body = &materials[0];
+        assert_eq!(body.name, "Body");
+        assert_eq!(body.diffuse, FloatColor::new(0.8, 0.2, 0.1));
+        assert_eq!(body.shininess, 96.0);
+        assert_eq!(
+            body.diffuse_map.as_deref(),
+            Some(Path::new("assets/textures/body.png"))
+        );
+        assert_eq!(
+            body.bump_map.as_deref(),
+            Some(Path::new("assets/body_normal.png"))
+        );
+        assert_eq!(body.to_material(Material::default()).specular, 0.5);
+
+        // Dissolved materials are blended, and illum 1 turns off specular highlights
+        let glass = materials[1].to_material(Material::default());
+        assert_eq!(glass.opacity, 0.25);
+        assert_eq!(glass.blend_mode, BlendMode::SourceOver);
+        assert_eq!(glass.specular, 0.0);
+    }
+
+    #[test]
+    fn invalid_values_give_line() {
+        assert_eq!(
+            read_mtl_lines("newmtl A\nKd 1.0 oops 1.0\n".as_bytes(), Path::new("")),
+            Err((2, ObjErrorReason::InvalidMaterial))
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -6,185 +7,387 @@ use tinyvec::ArrayVec;
 
 use crate::color::Color;
 use crate::mesh::Mesh;
+use crate::mtl::{read_mtl, MtlMaterial};
+use crate::polygon::triangulate_face;
 use crate::texture::Tex2;
 use crate::triangle::Face;
 use crate::vector::Vec3;
 
-#[derive(Debug)]
-struct FileFormatError;
+/// The list of elements a face index refers to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexKind {
+    Vertex,
+    Uv,
+    Normal,
+}
+
+impl std::fmt::Display for IndexKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Vertex => write!(f, "vertex"),
+            Self::Uv => write!(f, "texture coordinate"),
+            Self::Normal => write!(f, "normal"),
+        }
+    }
+}
 
-pub enum FaceParsingOutput {
-    One(Face),
-    Two((Face, Face)),
+/// Why an OBJ file could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ObjErrorReason {
+    OpenError,
+    ReadError,
+    InvalidVertex,
+    InvalidUv,
+    InvalidNormal,
+    InvalidFace,
+    InvalidMaterial,
+    /// The material library couldn't be read
+    MaterialLibrary(Box<ObjError<'static>>),
+    /// A face refers to an element that hasn't been defined. The index is as written in the
+    /// file, so it starts at 1 or is negative.
+    IndexOutOfRange {
+        kind: IndexKind,
+        index: i64,
+        count: usize,
+    },
 }
 
-fn read_vertex(line: &str) -> Result<Vec3, FileFormatError> {
-    let mut positions: [f32; 3] = [0.0; 3];
-
-    // A vertex position line should look like this:
-    // v <x> <y> <z>
-    for (i, position_str) in line
-        .split_ascii_whitespace()
-        .skip(1)
-        .enumerate()
-        .take_while(|(i, _)| *i < 3)
-    {
-        match position_str.parse::<f32>() {
-            Ok(pos) => {
-                positions[i] = pos;
+impl std::fmt::Display for ObjErrorReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OpenError => write!(f, "could not open file for reading"),
+            Self::ReadError => write!(f, "could not read line"),
+            Self::InvalidVertex => write!(f, "invalid vertex position"),
+            Self::InvalidUv => write!(f, "invalid texture coordinates"),
+            Self::InvalidNormal => write!(f, "invalid vertex normal"),
+            Self::InvalidFace => write!(f, "invalid face"),
+            Self::InvalidMaterial => write!(f, "invalid material property"),
+            Self::MaterialLibrary(err) => write!(f, "could not read material library {err}"),
+            Self::IndexOutOfRange { kind, index, count } => {
+                write!(
+                    f,
+                    "{kind} index {index} is out of range, only {count} have been defined"
+                )
             }
-            Err(_) => return Err(FileFormatError),
         }
     }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjError<'a> {
+    pub path: Cow<'a, str>,
+    /// Line of the file the error was found on, starting at 1
+    pub line: Option<usize>,
+    pub reason: ObjErrorReason,
+}
 
-    Ok(Vec3::new(positions[0], positions[1], positions[2]))
+impl ObjError<'_> {
+    pub fn into_owned(self) -> ObjError<'static> {
+        ObjError {
+            path: Cow::Owned(self.path.into_owned()),
+            line: self.line,
+            reason: self.reason,
+        }
+    }
 }
 
-fn read_uv(line: &str) -> Result<Tex2, FileFormatError> {
-    let mut uv: [f32; 2] = [0.0; 2];
-
-    // A vertex UV line should look like this:
-    // vt <u> <v>
-    for (i, uv_str) in line
-        .split_ascii_whitespace()
-        .skip(1)
-        .enumerate()
-        .take_while(|(i, _)| *i < 2)
-    {
-        match uv_str.parse::<f32>() {
-            Ok(tex_coord) => uv[i] = tex_coord,
-            Err(_) => return Err(FileFormatError),
+impl std::fmt::Display for ObjError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {line}): {}", self.path, self.reason),
+            None => write!(f, "{}: {}", self.path, self.reason),
         }
     }
+}
+
+/// Parses the numbers following a line's keyword, failing if there are fewer than `MIN`
+pub(crate) fn read_numbers<const MAX: usize, const MIN: usize>(
+    line: &str,
+) -> Option<ArrayVec<[f32; MAX]>> {
+    let mut numbers = ArrayVec::new();
+
+    for number_str in line.split_ascii_whitespace().skip(1).take(MAX) {
+        numbers.push(number_str.parse::<f32>().ok()?);
+    }
+
+    (numbers.len() >= MIN).then_some(numbers)
+}
+
+fn read_vertex(line: &str) -> Result<(Vec3, Option<Color>), ObjErrorReason> {
+    // A vertex position line should look like this, optionally followed by a color:
+    // v <x> <y> <z> [<r> <g> <b>]
+    let values = read_numbers::<6, 3>(line).ok_or(ObjErrorReason::InvalidVertex)?;
+
+    // Color channels go from 0 to 1
+    let color = (values.len() == 6).then(|| {
+        let [r, g, b] = [values[3], values[4], values[5]]
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+        Color::new(r, g, b)
+    });
+
+    Ok((Vec3::new(values[0], values[1], values[2]), color))
+}
+
+fn read_uv(line: &str) -> Result<Tex2, ObjErrorReason> {
+    // A vertex UV line should look like this, where v and w are optional and default to 0:
+    // vt <u> [<v> [<w>]]
+    let uv = read_numbers::<3, 1>(line).ok_or(ObjErrorReason::InvalidUv)?;
+    let v = uv.get(1).copied().unwrap_or(0.0);
 
     // Flip the v coordinate for compatibility with more .OBJ models
-    Ok(Tex2::new(uv[0], 1.0 - uv[1]))
+    Ok(Tex2::new(uv[0], 1.0 - v))
+}
+
+fn read_normal(line: &str) -> Result<Vec3, ObjErrorReason> {
+    // A vertex normal line should look like this:
+    // vn <x> <y> <z>
+    let normal = read_numbers::<3, 3>(line).ok_or(ObjErrorReason::InvalidNormal)?;
+
+    Ok(Vec3::new(normal[0], normal[1], normal[2]).normalized_or_zero())
+}
+
+/// Converts an index written in a face into an index into the mesh's list of elements. OBJ
+/// indices start at 1, and negative indices count back from the last element defined.
+fn resolve_index(index_str: &str, count: usize, kind: IndexKind) -> Result<u32, ObjErrorReason> {
+    let index = index_str
+        .parse::<i64>()
+        .map_err(|_| ObjErrorReason::InvalidFace)?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjErrorReason::IndexOutOfRange { kind, index, count });
+    }
+
+    u32::try_from(resolved).map_err(|_| ObjErrorReason::IndexOutOfRange { kind, index, count })
 }
 
+/// Reads the faces of a face line, and whether its vertices have texture coordinates. Faces
+/// without them have their UV indices set to 0. Polygons with more than three vertices are
+/// split into triangles.
 fn read_faces(
     line: &str,
-    num_vertices: u16,
-    num_vertex_uvs: u16,
-) -> Result<FaceParsingOutput, FileFormatError> {
-    let mut vertex_indices: ArrayVec<[u16; 4]> = ArrayVec::new();
-    let mut vertex_uvs: ArrayVec<[u16; 4]> = ArrayVec::new();
-
-    // A face line should look like this:
-    // f <vertex index>/<uv index> <vertex index>/<uv index> <vertex index>/<uv index> [vertex index]/[uv_index]
-    for (_, indices_str) in line
-        .split_ascii_whitespace()
-        .skip(1)
-        .enumerate()
-        .take_while(|(i, _)| *i < 4)
-    {
-        let mut vertex_index_str = indices_str.split('/');
+    vertices: &[Vec3],
+    num_vertex_uvs: usize,
+    num_vertex_normals: usize,
+) -> Result<(Vec<Face>, bool), ObjErrorReason> {
+    let mut vertex_indices = Vec::new();
+    let mut vertex_uvs = Vec::new();
+    let mut vertex_normals = Vec::new();
+
+    // A face line should look like this, with three or more vertices, where the UV and normal
+    // indices are optional:
+    // f <vertex index>[/[uv index][/normal index]] <vertex index>[/[uv index][/normal index]] <vertex index>[/[uv index][/normal index]] ...
+    for indices_str in line.split_ascii_whitespace().skip(1) {
+        let mut index_strs = indices_str.split('/');
 
         // Vertex positions
-        match vertex_index_str.next().unwrap().parse::<i32>() {
-            Ok(vertex_index) => {
-                if vertex_index > 0 {
-                    // Indices start at 1 for OBJ files
-                    vertex_indices.push((vertex_index as u16) - 1);
-                } else {
-                    vertex_indices.push(num_vertices - vertex_index as u16 - 1);
-                }
-            }
-            Err(_) => {
-                return Err(FileFormatError);
-            }
+        let vertex_index_str = index_strs.next().unwrap_or_default();
+        vertex_indices.push(resolve_index(
+            vertex_index_str,
+            vertices.len(),
+            IndexKind::Vertex,
+        )?);
+
+        // Vertex UVs, which are left empty in faces written like f 1//1
+        if let Some(uv_index_str) = index_strs.next().filter(|str| !str.is_empty()) {
+            vertex_uvs.push(resolve_index(uv_index_str, num_vertex_uvs, IndexKind::Uv)?);
         }
 
-        // Vertex UVs
-        if let Some(uv_index_str) = vertex_index_str.next() {
-            match uv_index_str.parse::<i32>() {
-                Ok(uv_index) => {
-                    if uv_index > 0 {
-                        vertex_uvs.push((uv_index as u16) - 1);
-                    } else {
-                        vertex_uvs.push(num_vertex_uvs - uv_index as u16 - 1);
-                    }
-                }
-                Err(_) => {
-                    return Err(FileFormatError);
-                }
-            }
+        // Vertex normals
+        if let Some(normal_index_str) = index_strs.next() {
+            vertex_normals.push(resolve_index(
+                normal_index_str,
+                num_vertex_normals,
+                IndexKind::Normal,
+            )?);
         }
     }
 
-    if vertex_indices.len() == 3 {
-        return Ok(FaceParsingOutput::One(Face::new(
-            vertex_indices[0],
-            vertex_indices[1],
-            vertex_indices[2],
-            vertex_uvs[0],
-            vertex_uvs[1],
-            vertex_uvs[2],
-            Color::new(0, 0xFF, 0xFF),
-        )));
-    } else if vertex_indices.len() == 4 {
-        return Ok(FaceParsingOutput::Two((
-            Face::new(
-                vertex_indices[0],
-                vertex_indices[1],
-                vertex_indices[2],
-                vertex_uvs[0],
-                vertex_uvs[1],
-                vertex_uvs[2],
-                Color::new(0, 0xFF, 0xFF),
-            ),
-            Face::new(
-                vertex_indices[2],
-                vertex_indices[3],
-                vertex_indices[0],
-                vertex_uvs[2],
-                vertex_uvs[3],
-                vertex_uvs[0],
-                Color::new(0, 0xFF, 0xFF),
-            ),
-        )));
-    } else {
-        return Err(FileFormatError);
+    if vertex_indices.len() < 3 {
+        return Err(ObjErrorReason::InvalidFace);
     }
+
+    // Normals and texture coordinates are only used if every vertex of the face has one
+    let has_normals = vertex_normals.len() == vertex_indices.len();
+    let has_uvs = vertex_uvs.len() == vertex_indices.len();
+
+    if !has_uvs {
+        vertex_uvs = vec![0; vertex_indices.len()];
+    }
+
+    let positions: Vec<Vec3> = vertex_indices
+        .iter()
+        .map(|&index| vertices[index as usize])
+        .collect();
+
+    let faces = triangulate_face(&positions)
+        .into_iter()
+        .map(|[a, b, c]| {
+            let face = Face::new(
+                vertex_indices[a],
+                vertex_indices[b],
+                vertex_indices[c],
+                vertex_uvs[a],
+                vertex_uvs[b],
+                vertex_uvs[c],
+                Color::new(0, 0xFF, 0xFF),
+            );
+
+            if has_normals {
+                face.with_normals(vertex_normals[a], vertex_normals[b], vertex_normals[c])
+            } else {
+                face
+            }
+        })
+        .collect();
+
+    Ok((faces, has_uvs))
 }
 
 impl Mesh {
-    pub fn from_obj(obj_file_path: &Path, rotation: Vec3, scale: Vec3, translation: Vec3) -> Self {
-        let obj_file = File::open(obj_file_path).expect("Could not open OBJ file for reading!");
-
-        let mut vertices = Vec::new();
-        let mut vertex_uvs: Vec<Tex2> = Vec::new();
-        let mut faces = Vec::new();
-
-        for potential_line in BufReader::new(obj_file).lines() {
-            let line = potential_line.expect("Could not read line from OBJ file!");
-
-            if line.starts_with("v ") {
-                let vertex = read_vertex(&line)
-                    .expect(format!("Could not read vertex from line {}!", &line).as_str());
-                vertices.push(vertex);
-            } else if line.starts_with("vt ") {
-                let uv = read_uv(&line)
-                    .expect(format!("Could not read vertex UV from line {}!", &line).as_str());
-                vertex_uvs.push(uv);
-            } else if line.starts_with("f ") {
-                let face_parsing_output =
-                    read_faces(&line, vertices.len() as u16, vertex_uvs.len() as u16)
-                        .expect(format!("Could not read face(s) from line {}!", &line).as_str());
-
-                match face_parsing_output {
-                    FaceParsingOutput::One(face) => {
-                        faces.push(face);
+    pub fn from_obj(
+        obj_file_path: &Path,
+        rotation: Vec3,
+        scale: Vec3,
+        translation: Vec3,
+    ) -> Result<Self, ObjError<'_>> {
+        let obj_file = File::open(obj_file_path).map_err(|_| ObjError {
+            path: obj_file_path.to_string_lossy(),
+            line: None,
+            reason: ObjErrorReason::OpenError,
+        })?;
+
+        let directory = obj_file_path.parent().unwrap_or(Path::new(""));
+
+        read_obj(
+            BufReader::new(obj_file),
+            directory,
+            rotation,
+            scale,
+            translation,
+        )
+        .map_err(|(line, reason)| ObjError {
+            path: obj_file_path.to_string_lossy(),
+            line: Some(line),
+            reason,
+        })
+    }
+}
+
+/// Reads a mesh from the lines of an OBJ file, or returns the line number an error was found
+/// on and why. Material libraries are looked for in `directory`.
+fn read_obj(
+    reader: impl BufRead,
+    directory: &Path,
+    rotation: Vec3,
+    scale: Vec3,
+    translation: Vec3,
+) -> Result<Mesh, (usize, ObjErrorReason)> {
+    let mut vertices = Vec::new();
+    let mut vertex_colors = Vec::new();
+    let mut has_vertex_colors = false;
+    let mut vertex_uvs: Vec<Tex2> = Vec::new();
+    let mut vertex_normals = Vec::new();
+    let mut faces = Vec::new();
+    let mut faces_without_uvs = Vec::new();
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    let mut current_material = None;
+    let mut warnings = Vec::new();
+
+    for (line_index, potential_line) in reader.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = potential_line.map_err(|_| (line_number, ObjErrorReason::ReadError))?;
+        let at_line = |reason| (line_number, reason);
+
+        if line.starts_with("v ") {
+            let (vertex, color) = read_vertex(&line).map_err(at_line)?;
+            vertices.push(vertex);
+
+            // Vertices without a color are white when others have one
+            has_vertex_colors |= color.is_some();
+            vertex_colors.push(color.unwrap_or(Color::new(0xFF, 0xFF, 0xFF)));
+        } else if line.starts_with("vt ") {
+            vertex_uvs.push(read_uv(&line).map_err(at_line)?);
+        } else if line.starts_with("vn ") {
+            vertex_normals.push(read_normal(&line).map_err(at_line)?);
+        } else if line.starts_with("f ") {
+            let (parsed_faces, has_uvs) =
+                read_faces(&line, &vertices, vertex_uvs.len(), vertex_normals.len())
+                    .map_err(at_line)?;
+
+            let first_face_index = faces.len();
+
+            // Faces using a material take its diffuse color
+            faces.extend(parsed_faces.into_iter().map(|face| match current_material {
+                Some(material_index) => Face {
+                    color: Color::from(materials[material_index as usize].diffuse),
+                    ..face.with_material(material_index)
+                },
+                None => face,
+            }));
+
+            if !has_uvs {
+                faces_without_uvs.extend(first_face_index..faces.len());
+            }
+        } else if let Some(mtl_file_names) = line.strip_prefix("mtllib ") {
+            // A line can name several libraries. Libraries that don't exist are skipped,
+            // leaving the faces using their materials to be drawn with the object's texture.
+            for mtl_file_name in mtl_file_names.split_ascii_whitespace() {
+                match read_mtl(&directory.join(mtl_file_name)) {
+                    Ok(library) => materials.extend(library),
+                    Err(err) if err.reason == ObjErrorReason::OpenError => {
+                        warnings.push(format!("skipped material library {err}"));
                     }
-                    FaceParsingOutput::Two(parsed_faces) => {
-                        faces.push(parsed_faces.0);
-                        faces.push(parsed_faces.1);
+                    Err(err) => {
+                        return Err(at_line(ObjErrorReason::MaterialLibrary(Box::new(
+                            err.into_owned(),
+                        ))))
                     }
                 }
             }
+        } else if let Some(material_name) = line.strip_prefix("usemtl ") {
+            // Faces using a material that isn't in a library are drawn without one
+            current_material = materials
+                .iter()
+                .position(|material| material.name == material_name.trim())
+                .map(|index| index as u32);
         }
+    }
+
+    // Faces without texture coordinates all use a UV added after the file's own, so UV
+    // indices written in the file still refer to the right coordinates
+    if !faces_without_uvs.is_empty() {
+        let default_uv = vertex_uvs.len() as u32;
+        vertex_uvs.push(Tex2::default());
 
-        Self::new(vertices, vertex_uvs, faces, rotation, scale, translation)
+        for face_index in faces_without_uvs {
+            let face = &mut faces[face_index];
+            (face.a_uv, face.b_uv, face.c_uv) = (default_uv, default_uv, default_uv);
+        }
     }
+
+    if !has_vertex_colors {
+        vertex_colors.clear();
+    }
+
+    Ok(Mesh::new(
+        vertices,
+        vertex_uvs,
+        vertex_normals,
+        faces,
+        rotation,
+        scale,
+        translation,
+    )
+    .with_vertex_colors(vertex_colors)
+    .with_materials(materials)
+    .with_warnings(warnings))
 }
 
 #[cfg(test)]
@@ -193,8 +396,8 @@ mod tests {
 
     fn assert_face_attributes(
         face: &Face,
-        expected_vertex_indices: [u16; 3],
-        expected_uv_indices: [u16; 3],
+        expected_vertex_indices: [u32; 3],
+        expected_uv_indices: [u32; 3],
     ) {
         assert_eq!(
             face.a, expected_vertex_indices[0],
@@ -229,7 +432,8 @@ mod tests {
             Vec3::default(),
             Vec3::splat(1.0),
             Vec3::default(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(
             model.vertices,
@@ -258,6 +462,24 @@ mod tests {
             "Texture coordinates are not correct"
         );
 
+        assert_eq!(
+            model.vertex_normals,
+            vec![
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+            ],
+            "Vertex normals are not correct"
+        );
+
+        assert!(
+            model.vertex_colors.is_empty(),
+            "Model without vertex colors has vertex colors"
+        );
+
         assert_eq!(
             model.faces.len(),
             12,
@@ -282,5 +504,157 @@ mod tests {
 
         assert_face_attributes(&model.faces[10], [6, 0, 4], [0, 1, 2]);
         assert_face_attributes(&model.faces[11], [4, 0, 2], [2, 1, 3]);
+
+        for (i, face) in model.faces.iter().enumerate() {
+            let normal = (i / 2) as u32;
+            assert_eq!(
+                face.normals,
+                Some([normal; 3]),
+                "Face normal indices are not correct"
+            );
+        }
+    }
+
+    fn read_obj_str(obj: &str) -> Result<Mesh, (usize, ObjErrorReason)> {
+        read_obj(
+            obj.as_bytes(),
+            Path::new("assets"),
+            Vec3::default(),
+            Vec3::splat(1.0),
+            Vec3::default(),
+        )
+    }
+
+    #[test]
+    fn faces_without_uvs_can_be_read() {
+        let model = read_obj_str(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nvn 0 0 1\nf 1 2 3\nf 1//1 2//1 3//1\nf 1/1 2/1 3/1\n",
+        )
+        .unwrap();
+
+        assert_eq!(model.faces.len(), 3);
+        assert_eq!(model.vertex_uvs.len(), 2);
+
+        // The file's own UV is kept where it's used, while the others use an added UV
+        assert_face_attributes(&model.faces[0], [0, 1, 2], [1, 1, 1]);
+        assert_face_attributes(&model.faces[1], [0, 1, 2], [1, 1, 1]);
+        assert_face_attributes(&model.faces[2], [0, 1, 2], [0, 0, 0]);
+        assert_eq!(model.faces[1].normals, Some([0; 3]));
+        assert_eq!(model.faces[0].normals, None);
+    }
+
+    #[test]
+    fn polygons_are_triangulated() {
+        // An L shaped hexagon with UVs and normals on every vertex
+        let model = read_obj_str(
+            "v 0 0 0\nv 2 0 0\nv 2 1 0\nv 1 1 0\nv 1 2 0\nv 0 2 0\nvt 0 0\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1 4/1/1 5/1/1 6/1/1\n",
+        )
+        .unwrap();
+
+        assert_eq!(model.faces.len(), 4);
+        assert!(model
+            .faces
+            .iter()
+            .all(|face| face.normals == Some([0; 3]) && face.a_uv == 0));
+    }
+
+    #[test]
+    fn meshes_can_have_more_than_65536_vertices() {
+        let mut obj = String::new();
+        for i in 0..70_000 {
+            obj.push_str(&format!("v {i} 0 0\n"));
+        }
+        obj.push_str("f 1 69999 70000\nf -1 -2 -70000\n");
+
+        let model = read_obj_str(&obj).unwrap();
+
+        assert_eq!(model.vertices.len(), 70_000);
+        assert_eq!(
+            [model.faces[0].a, model.faces[0].b, model.faces[0].c],
+            [0, 69_998, 69_999]
+        );
+        assert_eq!(
+            [model.faces[1].a, model.faces[1].b, model.faces[1].c],
+            [69_999, 69_998, 0]
+        );
+    }
+
+    #[test]
+    fn errors_give_line_and_reason() {
+        assert_eq!(
+            read_obj_str("v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n").err(),
+            Some((
+                5,
+                ObjErrorReason::IndexOutOfRange {
+                    kind: IndexKind::Vertex,
+                    index: 4,
+                    count: 3
+                }
+            ))
+        );
+        assert_eq!(
+            read_obj_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -1 -2 -4\n").err(),
+            Some((
+                4,
+                ObjErrorReason::IndexOutOfRange {
+                    kind: IndexKind::Vertex,
+                    index: -4,
+                    count: 3
+                }
+            ))
+        );
+        assert_eq!(
+            read_obj_str("v 0 0\n").err(),
+            Some((1, ObjErrorReason::InvalidVertex))
+        );
+        assert_eq!(
+            read_obj_str("v 0 0 0\nf 1 1\n").err(),
+            Some((2, ObjErrorReason::InvalidFace))
+        );
+
+        let error = Mesh::from_obj(
+            Path::new("assets/missing.obj"),
+            Vec3::default(),
+            Vec3::splat(1.0),
+            Vec3::default(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.reason, ObjErrorReason::OpenError);
+        assert_eq!(error.line, None);
+    }
+
+    #[test]
+    fn vertex_colors_can_be_read() {
+        assert_eq!(
+            read_vertex("v 1.0 2.0 3.0 1.0 0.5 0.0").unwrap(),
+            (Vec3::new(1.0, 2.0, 3.0), Some(Color::new(0xFF, 0x80, 0x00)))
+        );
+        assert_eq!(
+            read_vertex("v 1.0 2.0 3.0").unwrap(),
+            (Vec3::new(1.0, 2.0, 3.0), None)
+        );
+    }
+
+    #[test]
+    fn every_material_library_on_a_line_is_read() {
+        let model =
+            read_obj_str("mtllib missing.mtl materials_cube.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl glass\nf 1 2 3\n")
+                .unwrap();
+
+        assert_eq!(model.materials.len(), 3);
+        assert_eq!(model.faces[0].material, Some(2));
+
+        // Missing libraries are left for the caller to report
+        assert_eq!(model.warnings.len(), 1);
+        assert!(model.warnings[0].contains("missing.mtl"));
+    }
+
+    #[test]
+    fn uvs_can_have_one_to_three_values() {
+        assert_eq!(read_uv("vt 0.25").unwrap(), Tex2::new(0.25, 1.0));
+        assert_eq!(read_uv("vt 0.25 0.75").unwrap(), Tex2::new(0.25, 0.25));
+        assert_eq!(read_uv("vt 0.25 0.75 0.5").unwrap(), Tex2::new(0.25, 0.25));
+        assert_eq!(read_uv("vt"), Err(ObjErrorReason::InvalidUv));
     }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    polygon::{Polygon, PolygonVertex},
+    polygon::{ClipVertex, Polygon},
     vector::Vec4,
 };
 
@@ -31,63 +31,53 @@ impl Plane {
     }
 
     /// Clip a polygon against the plane (only works for convex polygons)
-    pub fn clip_polygon(&self, polygon: &Polygon) -> Polygon {
-        if polygon.vertices().len() == 0 {
+    pub fn clip_polygon<V: ClipVertex>(&self, polygon: &Polygon<V>) -> Polygon<V> {
+        if polygon.vertices().is_empty()
+            || polygon.vertices().len() == 1 && !self.point_inside(polygon.vertices()[0].position())
+        {
             return Polygon::default();
-        } else if polygon.vertices().len() == 1 && !self.point_inside(polygon.vertices()[0].pos) {
-            return Polygon::default();
-        } else if polygon.vertices().len() == 1 && self.point_inside(polygon.vertices()[0].pos) {
+        } else if polygon.vertices().len() == 1 {
             return polygon.clone();
         }
 
         let mut clipped_polygon = Polygon::default();
 
         let mut previous_vert = polygon.vertices()[polygon.vertices().len() - 1];
-        let mut previous_vert_in = self.point_inside(previous_vert.pos);
+        let mut previous_vert_in = self.point_inside(previous_vert.position());
         for &vert in polygon.vertices().iter() {
-            let vert_in = self.point_inside(vert.pos);
+            let vert_in = self.point_inside(vert.position());
 
             if previous_vert_in && !vert_in || !previous_vert_in && vert_in {
+                let (previous_pos, pos) = (previous_vert.position(), vert.position());
+
                 // Calculate the intersection point between the vertices on the plane
                 let t = match self {
                     Plane::Left => {
-                        (previous_vert.pos.w + previous_vert.pos.x)
-                            / ((previous_vert.pos.w + previous_vert.pos.x)
-                                - (vert.pos.w + vert.pos.x))
+                        (previous_pos.w + previous_pos.x)
+                            / ((previous_pos.w + previous_pos.x) - (pos.w + pos.x))
                     }
                     Plane::Right => {
-                        (previous_vert.pos.w - previous_vert.pos.x)
-                            / ((previous_vert.pos.w - previous_vert.pos.x)
-                                - (vert.pos.w - vert.pos.x))
+                        (previous_pos.w - previous_pos.x)
+                            / ((previous_pos.w - previous_pos.x) - (pos.w - pos.x))
                     }
                     Plane::Top => {
-                        (previous_vert.pos.w - previous_vert.pos.y)
-                            / ((previous_vert.pos.w - previous_vert.pos.y)
-                                - (vert.pos.w - vert.pos.y))
+                        (previous_pos.w - previous_pos.y)
+                            / ((previous_pos.w - previous_pos.y) - (pos.w - pos.y))
                     }
                     Plane::Bottom => {
-                        (previous_vert.pos.w + previous_vert.pos.y)
-                            / ((previous_vert.pos.w + previous_vert.pos.y)
-                                - (vert.pos.w + vert.pos.y))
+                        (previous_pos.w + previous_pos.y)
+                            / ((previous_pos.w + previous_pos.y) - (pos.w + pos.y))
                     }
                     Plane::Far => {
-                        (previous_vert.pos.w - previous_vert.pos.z)
-                            / ((previous_vert.pos.w - previous_vert.pos.z)
-                                - (vert.pos.w - vert.pos.z))
-                    }
-                    Plane::Near => previous_vert.pos.z / (previous_vert.pos.z - vert.pos.z),
-                    Plane::W => {
-                        (W_EPSILON - previous_vert.pos.w) / (previous_vert.pos.w - vert.pos.w)
+                        (previous_pos.w - previous_pos.z)
+                            / ((previous_pos.w - previous_pos.z) - (pos.w - pos.z))
                     }
+                    Plane::Near => previous_pos.z / (previous_pos.z - pos.z),
+                    Plane::W => (W_EPSILON - previous_pos.w) / (previous_pos.w - pos.w),
                 };
 
-                let intersection = previous_vert.pos + t * (vert.pos - previous_vert.pos);
-                let interpolated_uv = previous_vert.uv + t * (vert.uv - previous_vert.uv);
-
-                clipped_polygon.add_vertex(PolygonVertex {
-                    pos: intersection,
-                    uv: interpolated_uv,
-                });
+                // Every attribute of the vertex is interpolated to the intersection point
+                clipped_polygon.add_vertex(previous_vert.lerp(&vert, t));
             }
 
             if vert_in {
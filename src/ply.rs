@@ -0,0 +1,691 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+use crate::color::Color;
+use crate::mesh::Mesh;
+use crate::polygon::triangulate_face;
+use crate::texture::Tex2;
+use crate::triangle::Face;
+use crate::vector::Vec3;
+
+/// Why a PLY file could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlyErrorReason {
+    OpenError,
+    InvalidHeader,
+    /// The vertices are missing an x, y or z property
+    MissingPosition,
+    InvalidValue,
+    /// The file ends before all of the elements in its header
+    TruncatedFile,
+    /// A face has fewer than three vertices
+    InvalidFace,
+    /// A face refers to a vertex that doesn't exist
+    IndexOutOfRange {
+        index: i64,
+        count: usize,
+    },
+}
+
+impl std::fmt::Display for PlyErrorReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OpenError => write!(f, "could not open file for reading"),
+            Self::InvalidHeader => write!(f, "invalid header"),
+            Self::MissingPosition => write!(f, "vertices have no position"),
+            Self::InvalidValue => write!(f, "invalid value"),
+            Self::TruncatedFile => write!(f, "file ends before all of its elements"),
+            Self::InvalidFace => write!(f, "face has fewer than three vertices"),
+            Self::IndexOutOfRange { index, count } => {
+                write!(
+                    f,
+                    "vertex index {index} is out of range, only {count} have been defined"
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlyError<'a> {
+    pub path: Cow<'a, str>,
+    /// Line the error was found on, starting at 1. Only known for the header and the body of
+    /// ASCII files.
+    pub line: Option<usize>,
+    pub reason: PlyErrorReason,
+}
+
+impl PlyError<'_> {
+    pub fn into_owned(self) -> PlyError<'static> {
+        PlyError {
+            path: Cow::Owned(self.path.into_owned()),
+            line: self.line,
+            reason: self.reason,
+        }
+    }
+}
+
+impl std::fmt::Display for PlyError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {line}): {}", self.path, self.reason),
+            None => write!(f, "{}: {}", self.path, self.reason),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(Self::Int8),
+            "uchar" | "uint8" => Some(Self::UInt8),
+            "short" | "int16" => Some(Self::Int16),
+            "ushort" | "uint16" => Some(Self::UInt16),
+            "int" | "int32" => Some(Self::Int32),
+            "uint" | "uint32" => Some(Self::UInt32),
+            "float" | "float32" => Some(Self::Float32),
+            "double" | "float64" => Some(Self::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Int8 | Self::UInt8 => 1,
+            Self::Int16 | Self::UInt16 => 2,
+            Self::Int32 | Self::UInt32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PropertyKind {
+    Scalar(ScalarType),
+    /// A count followed by that many items, such as the vertex indices of a face
+    List {
+        count: ScalarType,
+        item: ScalarType,
+    },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property_index(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    /// Number of lines in the header, including "end_header"
+    lines: usize,
+    /// Offset of the first byte after the header
+    size: usize,
+}
+
+impl Mesh {
+    /// Reads an ASCII or binary PLY file, including vertex normals, texture coordinates and
+    /// colors when the file has them
+    pub fn from_ply(
+        ply_file_path: &Path,
+        rotation: Vec3,
+        scale: Vec3,
+        translation: Vec3,
+    ) -> Result<Self, PlyError<'_>> {
+        let bytes = fs::read(ply_file_path).map_err(|_| PlyError {
+            path: ply_file_path.to_string_lossy(),
+            line: None,
+            reason: PlyErrorReason::OpenError,
+        })?;
+
+        read_ply(&bytes, rotation, scale, translation).map_err(|(line, reason)| PlyError {
+            path: ply_file_path.to_string_lossy(),
+            line,
+            reason,
+        })
+    }
+}
+
+fn read_header(bytes: &[u8]) -> Result<Header, (Option<usize>, PlyErrorReason)> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+    let mut line_number = 0;
+
+    loop {
+        line_number += 1;
+        let invalid = (Some(line_number), PlyErrorReason::InvalidHeader);
+
+        let line_length = bytes[offset..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or(invalid.clone())?;
+        let line = std::str::from_utf8(&bytes[offset..offset + line_length])
+            .map_err(|_| invalid.clone())?
+            .trim();
+        offset += line_length + 1;
+
+        let mut words = line.split_ascii_whitespace();
+        let keyword = words.next().unwrap_or_default();
+
+        if line_number == 1 {
+            if line != "ply" {
+                return Err(invalid);
+            }
+            continue;
+        }
+
+        match keyword {
+            "format" => {
+                format = Some(match words.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(invalid),
+                });
+            }
+            "element" => {
+                let (Some(name), Some(count)) = (words.next(), words.next()) else {
+                    return Err(invalid);
+                };
+
+                elements.push(Element {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| invalid.clone())?,
+                    properties: Vec::new(),
+                });
+            }
+            "property" => {
+                let element = elements.last_mut().ok_or(invalid.clone())?;
+
+                let words: Vec<&str> = words.collect();
+                let (kind, name) = match words[..] {
+                    ["list", count, item, name] => (
+                        ScalarType::parse(count)
+                            .zip(ScalarType::parse(item))
+                            .map(|(count, item)| PropertyKind::List { count, item }),
+                        name,
+                    ),
+                    [scalar, name] => (ScalarType::parse(scalar).map(PropertyKind::Scalar), name),
+                    _ => return Err(invalid),
+                };
+
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: kind.ok_or(invalid)?,
+                });
+            }
+            "end_header" => {
+                return Ok(Header {
+                    format: format.ok_or(invalid)?,
+                    elements,
+                    lines: line_number,
+                    size: offset,
+                });
+            }
+            // Comments and anything else unknown are skipped
+            _ => {}
+        }
+    }
+}
+
+/// Reads the values of each element in a PLY file's body, in the file's format
+trait ValueReader {
+    /// Moves on to the next element, returning the line it's on for ASCII files
+    fn start_element(&mut self) -> Result<Option<usize>, PlyErrorReason>;
+
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, PlyErrorReason>;
+}
+
+struct AsciiReader<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    values: std::str::SplitAsciiWhitespace<'a>,
+    /// Number of lines before the body
+    first_line: usize,
+}
+
+impl ValueReader for AsciiReader<'_> {
+    fn start_element(&mut self) -> Result<Option<usize>, PlyErrorReason> {
+        // Each element is written on its own line
+        loop {
+            let (line_index, line) = self.lines.next().ok_or(PlyErrorReason::TruncatedFile)?;
+
+            if !line.trim().is_empty() {
+                self.values = line.split_ascii_whitespace();
+                return Ok(Some(self.first_line + line_index + 1));
+            }
+        }
+    }
+
+    fn read(&mut self, _: ScalarType) -> Result<f64, PlyErrorReason> {
+        self.values
+            .next()
+            .ok_or(PlyErrorReason::InvalidValue)?
+            .parse()
+            .map_err(|_| PlyErrorReason::InvalidValue)
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+}
+
+impl ValueReader for BinaryReader<'_> {
+    fn start_element(&mut self) -> Result<Option<usize>, PlyErrorReason> {
+        Ok(None)
+    }
+
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, PlyErrorReason> {
+        let size = scalar_type.size();
+        if self.bytes.len() < size {
+            return Err(PlyErrorReason::TruncatedFile);
+        }
+
+        let (value, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+
+        // Copy the value into an 8 byte array in native byte order
+        let mut native = [0; 8];
+        native[..size].copy_from_slice(value);
+        if self.little_endian != cfg!(target_endian = "little") {
+            native[..size].reverse();
+        }
+
+        let bytes_of = |size: usize| -> &[u8] { &native[..size] };
+        Ok(match scalar_type {
+            ScalarType::Int8 => i8::from_ne_bytes(bytes_of(1).try_into().unwrap()) as f64,
+            ScalarType::UInt8 => u8::from_ne_bytes(bytes_of(1).try_into().unwrap()) as f64,
+            ScalarType::Int16 => i16::from_ne_bytes(bytes_of(2).try_into().unwrap()) as f64,
+            ScalarType::UInt16 => u16::from_ne_bytes(bytes_of(2).try_into().unwrap()) as f64,
+            ScalarType::Int32 => i32::from_ne_bytes(bytes_of(4).try_into().unwrap()) as f64,
+            ScalarType::UInt32 => u32::from_ne_bytes(bytes_of(4).try_into().unwrap()) as f64,
+            ScalarType::Float32 => f32::from_ne_bytes(bytes_of(4).try_into().unwrap()) as f64,
+            ScalarType::Float64 => f64::from_ne_bytes(native),
+        })
+    }
+}
+
+/// Reads the properties of one element into `values`, with the items of a list property
+/// one after another
+fn read_element(
+    reader: &mut impl ValueReader,
+    element: &Element,
+    values: &mut [Vec<f64>],
+) -> Result<(), PlyErrorReason> {
+    for (property, values) in element.properties.iter().zip(values.iter_mut()) {
+        values.clear();
+
+        match property.kind {
+            PropertyKind::Scalar(scalar_type) => values.push(reader.read(scalar_type)?),
+            PropertyKind::List { count, item } => {
+                let count = reader.read(count)?;
+                if count < 0.0 {
+                    return Err(PlyErrorReason::InvalidValue);
+                }
+
+                for _ in 0..count as usize {
+                    values.push(reader.read(item)?);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a mesh from the contents of a PLY file, or returns the line number an error was
+/// found on, if known, and why
+fn read_ply(
+    bytes: &[u8],
+    rotation: Vec3,
+    scale: Vec3,
+    translation: Vec3,
+) -> Result<Mesh, (Option<usize>, PlyErrorReason)> {
+    let header = read_header(bytes)?;
+    let body = &bytes[header.size..];
+
+    match header.format {
+        Format::Ascii => {
+            let text =
+                std::str::from_utf8(body).map_err(|_| (None, PlyErrorReason::InvalidValue))?;
+            let mut reader = AsciiReader {
+                lines: text.lines().enumerate(),
+                values: "".split_ascii_whitespace(),
+                first_line: header.lines,
+            };
+
+            read_body(&mut reader, &header.elements, rotation, scale, translation)
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+            let mut reader = BinaryReader {
+                bytes: body,
+                little_endian: header.format == Format::BinaryLittleEndian,
+            };
+
+            read_body(&mut reader, &header.elements, rotation, scale, translation)
+        }
+    }
+}
+
+fn read_body(
+    reader: &mut impl ValueReader,
+    elements: &[Element],
+    rotation: Vec3,
+    scale: Vec3,
+    translation: Vec3,
+) -> Result<Mesh, (Option<usize>, PlyErrorReason)> {
+    let mut vertices = Vec::new();
+    let mut vertex_normals = Vec::new();
+    let mut vertex_uvs = Vec::new();
+    let mut vertex_colors = Vec::new();
+    let mut faces = Vec::new();
+
+    for element in elements {
+        let mut values = vec![Vec::new(); element.properties.len()];
+        let property = |names: &[&str]| element.property_index(names);
+
+        // Elements other than vertices and faces are read and skipped
+        match element.name.as_str() {
+            "vertex" => {
+                let (Some(x), Some(y), Some(z)) =
+                    (property(&["x"]), property(&["y"]), property(&["z"]))
+                else {
+                    return Err((None, PlyErrorReason::MissingPosition));
+                };
+                let normal = property(&["nx"])
+                    .zip(property(&["ny"]))
+                    .zip(property(&["nz"]))
+                    .map(|((nx, ny), nz)| [nx, ny, nz]);
+                let uv = property(&["u", "s", "texture_u", "texture_s"]).zip(property(&[
+                    "v",
+                    "t",
+                    "texture_v",
+                    "texture_t",
+                ]));
+                let color = property(&["red", "r"])
+                    .zip(property(&["green", "g"]))
+                    .zip(property(&["blue", "b"]))
+                    .map(|((r, g), b)| [r, g, b, property(&["alpha", "a"]).unwrap_or(usize::MAX)]);
+
+                // Integer channels go from 0 to 255, and floating point channels from 0 to 1
+                let color_scale = |index: usize| match element.properties[index].kind {
+                    PropertyKind::Scalar(scalar_type) if scalar_type.is_float() => 255.0,
+                    _ => 1.0,
+                };
+
+                for _ in 0..element.count {
+                    let line = reader.start_element().map_err(|reason| (None, reason))?;
+                    read_element(reader, element, &mut values).map_err(|reason| (line, reason))?;
+
+                    // Every property is a scalar, so has exactly one value
+                    let value = |index: usize| {
+                        values[index]
+                            .first()
+                            .copied()
+                            .ok_or((line, PlyErrorReason::InvalidValue))
+                    };
+
+                    vertices.push(Vec3::new(
+                        value(x)? as f32,
+                        value(y)? as f32,
+                        value(z)? as f32,
+                    ));
+
+                    if let Some([nx, ny, nz]) = normal {
+                        let normal =
+                            Vec3::new(value(nx)? as f32, value(ny)? as f32, value(nz)? as f32);
+                        vertex_normals.push(normal.normalized_or_zero());
+                    }
+
+                    if let Some((u, v)) = uv {
+                        // Flip the v coordinate, like OBJ texture coordinates
+                        vertex_uvs.push(Tex2::new(value(u)? as f32, 1.0 - value(v)? as f32));
+                    }
+
+                    if let Some([r, g, b, a]) = color {
+                        let channel = |index: usize| -> Result<u8, _> {
+                            if index == usize::MAX {
+                                return Ok(0xFF);
+                            }
+
+                            Ok((value(index)? * color_scale(index))
+                                .clamp(0.0, 255.0)
+                                .round() as u8)
+                        };
+
+                        vertex_colors.push(Color::rgba(
+                            channel(r)?,
+                            channel(g)?,
+                            channel(b)?,
+                            channel(a)?,
+                        ));
+                    }
+                }
+            }
+            "face" => {
+                let indices = property(&["vertex_indices", "vertex_index"]);
+
+                for _ in 0..element.count {
+                    let line = reader.start_element().map_err(|reason| (None, reason))?;
+                    read_element(reader, element, &mut values).map_err(|reason| (line, reason))?;
+
+                    let Some(indices) = indices.map(|index| &values[index]) else {
+                        continue;
+                    };
+
+                    faces.extend(
+                        read_face(
+                            indices,
+                            &vertices,
+                            !vertex_uvs.is_empty(),
+                            !vertex_normals.is_empty(),
+                        )
+                        .map_err(|reason| (line, reason))?,
+                    );
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    let line = reader.start_element().map_err(|reason| (None, reason))?;
+                    read_element(reader, element, &mut values).map_err(|reason| (line, reason))?;
+                }
+            }
+        }
+    }
+
+    // Vertices without texture coordinates all use a single default UV
+    if vertex_uvs.is_empty() {
+        vertex_uvs.push(Tex2::default());
+    }
+
+    Ok(Mesh::new(
+        vertices,
+        vertex_uvs,
+        vertex_normals,
+        faces,
+        rotation,
+        scale,
+        translation,
+    )
+    .with_vertex_colors(vertex_colors))
+}
+
+/// Triangulates a face given its vertex indices. Vertices are expected to be read before
+/// faces, as every common exporter writes them.
+fn read_face(
+    indices: &[f64],
+    vertices: &[Vec3],
+    has_uvs: bool,
+    has_normals: bool,
+) -> Result<Vec<Face>, PlyErrorReason> {
+    if indices.len() < 3 {
+        return Err(PlyErrorReason::InvalidFace);
+    }
+
+    let indices = indices
+        .iter()
+        .map(|&index| {
+            if index >= 0.0 && (index as usize) < vertices.len() {
+                Ok(index as u32)
+            } else {
+                Err(PlyErrorReason::IndexOutOfRange {
+                    index: index as i64,
+                    count: vertices.len(),
+                })
+            }
+        })
+        .collect::<Result<Vec<u32>, _>>()?;
+
+    let positions: Vec<Vec3> = indices
+        .iter()
+        .map(|&index| vertices[index as usize])
+        .collect();
+
+    Ok(triangulate_face(&positions)
+        .into_iter()
+        .map(|[a, b, c]| {
+            let [a, b, c] = [indices[a], indices[b], indices[c]];
+            let uv = |index| if has_uvs { index } else { 0 };
+            let face = Face::new(a, b, c, uv(a), uv(b), uv(c), Color::new(0, 0xFF, 0xFF));
+
+            if has_normals {
+                face.with_normals(a, b, c)
+            } else {
+                face
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_ply_bytes(bytes: &[u8]) -> Result<Mesh, (Option<usize>, PlyErrorReason)> {
+        read_ply(bytes, Vec3::default(), Vec3::splat(1.0), Vec3::default())
+    }
+
+    #[test]
+    fn ascii_files_with_vertex_colors_can_be_read() {
+        let mesh = read_ply_bytes(
+            b"ply\n\
+              format ascii 1.0\n\
+              comment a red and blue square\n\
+              element vertex 4\n\
+              property float x\n\
+              property float y\n\
+              property float z\n\
+              property uchar red\n\
+              property uchar green\n\
+              property uchar blue\n\
+              element face 1\n\
+              property list uchar int vertex_indices\n\
+              end_header\n\
+              0 0 0 255 0 0\n\
+              1 0 0 255 0 0\n\
+              1 1 0 0 0 255\n\
+              0 1 0 0 0 255\n\
+              4 0 1 2 3\n",
+        )
+        .unwrap_or_else(|(line, reason)| panic!("line {line:?}: {reason}"));
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.faces.len(), 2);
+        assert_eq!(mesh.vertex_colors[0], Color::new(255, 0, 0));
+        assert_eq!(mesh.vertex_colors[3], Color::new(0, 0, 255));
+    }
+
+    #[test]
+    fn binary_files_can_be_read() {
+        let mut bytes = b"ply\n\
+                          format binary_big_endian 1.0\n\
+                          element vertex 3\n\
+                          property double x\n\
+                          property double y\n\
+                          property double z\n\
+                          property float red\n\
+                          property float green\n\
+                          property float blue\n\
+                          element face 1\n\
+                          property list uchar ushort vertex_indices\n\
+                          end_header\n"
+            .to_vec();
+        for [x, y] in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0f64]] {
+            bytes.extend([x, y, 0.0].iter().flat_map(|value| value.to_be_bytes()));
+            bytes.extend(
+                [0.0, 0.5, 1.0f32]
+                    .iter()
+                    .flat_map(|value| value.to_be_bytes()),
+            );
+        }
+        bytes.push(3);
+        bytes.extend([0u16, 1, 2].iter().flat_map(|index| index.to_be_bytes()));
+
+        let mesh = read_ply_bytes(&bytes).unwrap_or_else(|(_, reason)| panic!("{reason}"));
+
+        assert_eq!(mesh.vertices[1], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.vertex_colors[2], Color::new(0, 128, 255));
+        let face = &mesh.faces[0];
+        assert_eq!([face.a, face.b, face.c], [0, 1, 2]);
+    }
+
+    #[test]
+    fn errors_give_line_and_reason() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                      property float y\nproperty float z\nelement face 1\n\
+                      property list uchar int vertex_indices\nend_header\n";
+
+        assert_eq!(
+            read_ply_bytes(format!("{header}0 0 0\n1 0 0\n0 1 0\n3 0 1 7\n").as_bytes()).err(),
+            Some((
+                Some(13),
+                PlyErrorReason::IndexOutOfRange { index: 7, count: 3 }
+            ))
+        );
+        assert_eq!(
+            read_ply_bytes(format!("{header}0 0 0\n1 oops 0\n").as_bytes()).err(),
+            Some((Some(11), PlyErrorReason::InvalidValue))
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use tinyvec::ArrayVec;
 
-use crate::{texture::Tex2, vector::Vec4};
+use crate::{
+    attributes::Attributes,
+    texture::Tex2,
+    triangle::VERTEX_ATTRIBUTES,
+    vector::{Vec2, Vec3, Vec4},
+};
 
 // Each time a plane clips a triangle, a maximum of one extra vertex can be produced
 // Since a triangle has 3 vertices and we're clipping against the 6 frustum planes
@@ -8,32 +13,65 @@ use crate::{texture::Tex2, vector::Vec4};
 pub const MAX_POLYGON_VERTS: usize = 9;
 pub const MAX_TRIANGLES: usize = MAX_POLYGON_VERTS - 2;
 
+/// A vertex that can be clipped against the view frustum
+pub trait ClipVertex: Copy + Default {
+    /// Position in clip space, or in screen space once projected
+    fn position(&self) -> Vec4;
+
+    fn set_position(&mut self, position: Vec4);
+
+    /// Vertex a fraction `t` of the way from this vertex to `other`, used to create new
+    /// vertices where an edge crosses a clipping plane
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+/// A polygon vertex carrying `N` attributes, which are interpolated along with its position
+/// and texture coordinate when the polygon is clipped
 #[derive(Clone, Copy, Default, Debug)]
-pub struct PolygonVertex {
+pub struct PolygonVertex<const N: usize = VERTEX_ATTRIBUTES> {
     pub pos: Vec4,
     pub uv: Tex2,
+    pub attributes: Attributes<N>,
+}
+
+impl<const N: usize> ClipVertex for PolygonVertex<N> {
+    fn position(&self) -> Vec4 {
+        self.pos
+    }
+
+    fn set_position(&mut self, position: Vec4) {
+        self.pos = position;
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            pos: self.pos + t * (other.pos - self.pos),
+            uv: self.uv + t * (other.uv - self.uv),
+            attributes: self.attributes + t * (other.attributes - self.attributes),
+        }
+    }
 }
 
 #[derive(Clone, Default, Debug)]
-pub struct Polygon {
-    verts: ArrayVec<[PolygonVertex; MAX_POLYGON_VERTS]>,
+pub struct Polygon<V: ClipVertex = PolygonVertex> {
+    verts: ArrayVec<[V; MAX_POLYGON_VERTS]>,
 }
 
-impl Polygon {
-    pub fn new(verts: ArrayVec<[PolygonVertex; MAX_POLYGON_VERTS]>) -> Self {
+impl<V: ClipVertex> Polygon<V> {
+    pub fn new(verts: ArrayVec<[V; MAX_POLYGON_VERTS]>) -> Self {
         Self { verts }
     }
 
-    pub fn vertices(&self) -> &[PolygonVertex] {
+    pub fn vertices(&self) -> &[V] {
         &self.verts
     }
 
-    pub fn add_vertex(&mut self, vert: PolygonVertex) {
+    pub fn add_vertex(&mut self, vert: V) {
         self.verts.push(vert);
     }
 
     /// Fan triangulate the polygon (only works for convex polygons)
-    pub fn triangulate(&self) -> ArrayVec<[[PolygonVertex; 3]; MAX_TRIANGLES]> {
+    pub fn triangulate(&self) -> ArrayVec<[[V; 3]; MAX_TRIANGLES]> {
         let mut triangles = ArrayVec::new();
 
         if self.verts.len() < 3 {
@@ -49,3 +87,155 @@ impl Polygon {
         triangles
     }
 }
+
+/// Splits a polygon into triangles, given the positions of its vertices in order. Convex
+/// polygons are split into a fan around their first vertex, while concave polygons are split
+/// by ear clipping. Returns the triangles as indices into `positions`, with the polygon's
+/// winding.
+pub fn triangulate_face(positions: &[Vec3]) -> Vec<[usize; 3]> {
+    if positions.len() < 3 {
+        return Vec::new();
+    }
+
+    let fan = || (2..positions.len()).map(|i| [0, i - 1, i]).collect();
+
+    // Flatten the polygon onto the axis plane it is most aligned with, using Newell's method
+    // to find its normal
+    let mut normal = Vec3::default();
+    for (i, current) in positions.iter().enumerate() {
+        let next = positions[(i + 1) % positions.len()];
+
+        normal.x += (current.y - next.y) * (current.z + next.z);
+        normal.y += (current.z - next.z) * (current.x + next.x);
+        normal.z += (current.x - next.x) * (current.y + next.y);
+    }
+
+    let points: Vec<Vec2> = positions
+        .iter()
+        .map(|position| {
+            if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() {
+                Vec2::new(position.y, position.z)
+            } else if normal.y.abs() >= normal.z.abs() {
+                Vec2::new(position.z, position.x)
+            } else {
+                Vec2::new(position.x, position.y)
+            }
+        })
+        .collect();
+
+    // Corners turning the same way as the polygon winds are convex
+    let area: f32 = (0..points.len())
+        .map(|i| points[i].cross(points[(i + 1) % points.len()]))
+        .sum();
+    let turn = |a: Vec2, b: Vec2, c: Vec2| (b - a).cross(c - b) * area.signum();
+
+    if area == 0.0
+        || (0..points.len()).all(|i| {
+            let previous = points[(i + points.len() - 1) % points.len()];
+            turn(previous, points[i], points[(i + 1) % points.len()]) >= 0.0
+        })
+    {
+        return fan();
+    }
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+
+    while remaining.len() > 3 {
+        let len = remaining.len();
+
+        let ear = (0..len).find(|&i| {
+            let [a, b, c] = [(i + len - 1) % len, i, (i + 1) % len].map(|j| remaining[j]);
+
+            // An ear is a convex corner with no other vertex inside the triangle it cuts off
+            turn(points[a], points[b], points[c]) > 0.0
+                && remaining
+                    .iter()
+                    .filter(|&&j| j != a && j != b && j != c)
+                    .all(|&j| !point_in_triangle(points[j], [points[a], points[b], points[c]]))
+        });
+
+        // Self-intersecting polygons can run out of ears, so fan out what's left
+        let Some(ear) = ear else {
+            for i in 2..len {
+                triangles.push([remaining[0], remaining[i - 1], remaining[i]]);
+            }
+
+            return triangles;
+        };
+
+        triangles.push([
+            remaining[(ear + len - 1) % len],
+            remaining[ear],
+            remaining[(ear + 1) % len],
+        ]);
+        remaining.remove(ear);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    triangles
+}
+
+/// Whether a point is inside or on the edge of a triangle, in either winding
+fn point_in_triangle(point: Vec2, [a, b, c]: [Vec2; 3]) -> bool {
+    let ab = (b - a).cross(point - a);
+    let bc = (c - b).cross(point - b);
+    let ca = (a - c).cross(point - c);
+
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0) || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Area of a triangulation, in the xy plane
+    fn area(positions: &[Vec3], triangles: &[[usize; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                let [a, b, c] = [a, b, c].map(|i| Vec2::new(positions[i].x, positions[i].y));
+                (b - a).cross(c - a) / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn convex_polygons_are_fanned() {
+        let hexagon: Vec<Vec3> = (0..6)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::TAU / 6.0;
+                Vec3::new(angle.cos(), angle.sin(), 0.0)
+            })
+            .collect();
+
+        assert_eq!(
+            triangulate_face(&hexagon),
+            vec![[0, 1, 2], [0, 2, 3], [0, 3, 4], [0, 4, 5]]
+        );
+    }
+
+    #[test]
+    fn concave_polygons_are_ear_clipped() {
+        // An L shape, whose fan from the first vertex would cover the notch
+        let l_shape = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(2.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 2.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+        ];
+
+        let triangles = triangulate_face(&l_shape);
+
+        assert_eq!(triangles.len(), 4);
+        assert_eq!(area(&l_shape, &triangles), 3.0);
+
+        // Every triangle keeps the polygon's counterclockwise winding
+        for &[a, b, c] in triangles.iter() {
+            assert!(area(&l_shape, &[[a, b, c]]) > 0.0);
+        }
+    }
+}
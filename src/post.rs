@@ -0,0 +1,436 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::color::{Color, FloatColor, LinearColor};
+use crate::color_buffer::ColorBuffer;
+use crate::depth_buffer::DepthBuffer;
+use crate::obj::read_numbers;
+
+/// A full screen pass over a finished frame. Effects run after the frame has been tone
+/// mapped, so they see the same sRGB colors that are displayed.
+pub trait PostEffect {
+    fn apply(&self, color_buffer: &mut ColorBuffer, depth_buffer: &DepthBuffer);
+}
+
+/// Replaces every pixel with the color returned for it
+fn map_pixels(color_buffer: &mut ColorBuffer, mut map: impl FnMut(u16, u16, Color) -> Color) {
+    let width = color_buffer.width() as usize;
+
+    for i in 0..color_buffer.buffer().len() {
+        let color = Color::from(color_buffer.buffer()[i]);
+        let mapped = map((i % width) as u16, (i / width) as u16, color);
+
+        color_buffer.set_index(i, mapped);
+    }
+}
+
+/// Darkens the frame towards its corners
+#[derive(Clone, Copy, Debug)]
+pub struct Vignette {
+    /// How much the corners are darkened, from 0 (not at all) to 1 (black)
+    pub strength: f32,
+    /// Distance from the center where the darkening starts, as a fraction of the distance
+    /// to the corners
+    pub radius: f32,
+}
+
+impl PostEffect for Vignette {
+    fn apply(&self, color_buffer: &mut ColorBuffer, _depth_buffer: &DepthBuffer) {
+        let center_x = color_buffer.width() as f32 / 2.0;
+        let center_y = color_buffer.height() as f32 / 2.0;
+        let corner_distance = (center_x * center_x + center_y * center_y).sqrt();
+
+        map_pixels(color_buffer, |x, y, color| {
+            let dx = x as f32 + 0.5 - center_x;
+            let dy = y as f32 + 0.5 - center_y;
+            let distance = (dx * dx + dy * dy).sqrt() / corner_distance;
+
+            // Smoothstep from the radius out to the corners
+            let t =
+                ((distance - self.radius) / (1.0 - self.radius).max(f32::EPSILON)).clamp(0.0, 1.0);
+            let falloff = t * t * (3.0 - 2.0 * t);
+
+            // Darken the light rather than the encoded values, so the falloff looks even
+            let factor = 1.0 - self.strength.clamp(0.0, 1.0) * falloff;
+            (LinearColor::from_srgb(color) * FloatColor::splat(factor)).to_srgb()
+        });
+    }
+}
+
+/// Removes the color from the frame, keeping the brightness of each pixel
+#[derive(Clone, Copy, Debug)]
+pub struct Grayscale;
+
+impl PostEffect for Grayscale {
+    fn apply(&self, color_buffer: &mut ColorBuffer, _depth_buffer: &DepthBuffer) {
+        map_pixels(color_buffer, |_, _, color| {
+            let linear = LinearColor::from_srgb(color);
+
+            // Rec. 709 luminance, which is only meaningful in linear light
+            let luminance = 0.2126 * linear.r + 0.7152 * linear.g + 0.0722 * linear.b;
+            LinearColor::new(luminance, luminance, luminance, linear.a).to_srgb()
+        });
+    }
+}
+
+/// Sharpens edges by subtracting a blurred copy of the frame (an unsharp mask)
+#[derive(Clone, Copy, Debug)]
+pub struct Sharpen {
+    /// How strongly edges are sharpened. 0 leaves the frame as it is.
+    pub amount: f32,
+}
+
+impl PostEffect for Sharpen {
+    fn apply(&self, color_buffer: &mut ColorBuffer, _depth_buffer: &DepthBuffer) {
+        let source = color_buffer.buffer().to_vec();
+        let width = color_buffer.width() as usize;
+        let height = color_buffer.height() as usize;
+
+        // Pixels past the edges of the frame repeat the edge pixels
+        let source_pixel =
+            |x: usize, y: usize| FloatColor::from(Color::from(source[y * width + x]));
+
+        map_pixels(color_buffer, |x, y, color| {
+            let (x, y) = (x as usize, y as usize);
+            let center = FloatColor::from(color);
+            let neighbors = source_pixel(x.saturating_sub(1), y)
+                + source_pixel((x + 1).min(width - 1), y)
+                + source_pixel(x, y.saturating_sub(1))
+                + source_pixel(x, (y + 1).min(height - 1));
+
+            let sharpened = center + (center * 4.0 - neighbors) * self.amount;
+            let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+            Color::rgba(
+                channel(sharpened.r),
+                channel(sharpened.g),
+                channel(sharpened.b),
+                color.a,
+            )
+        });
+    }
+}
+
+/// Grades the frame by looking each color up in a 3D lookup table
+pub struct ColorGrading {
+    pub lut: Lut3d,
+    /// How much of the graded color is used, from 0 (none) to 1 (all of it)
+    pub intensity: f32,
+}
+
+impl PostEffect for ColorGrading {
+    fn apply(&self, color_buffer: &mut ColorBuffer, _depth_buffer: &DepthBuffer) {
+        map_pixels(color_buffer, |_, _, color| {
+            let original = FloatColor::from(color);
+            let graded = self.lut.sample(original);
+            let mixed = original + (graded - original) * self.intensity.clamp(0.0, 1.0);
+            let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+            Color::rgba(
+                channel(mixed.r),
+                channel(mixed.g),
+                channel(mixed.b),
+                color.a,
+            )
+        });
+    }
+}
+
+/// Largest LUT size that will be read, which keeps a corrupt size from allocating gigabytes
+const MAX_LUT_SIZE: usize = 256;
+
+/// A 3D color lookup table, mapping each input color to an output color. Colors between the
+/// table's entries are interpolated trilinearly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut3d {
+    /// Number of entries along each axis
+    size: usize,
+    /// Input colors mapped to the first and last entries along each axis
+    domain_min: FloatColor,
+    domain_max: FloatColor,
+    /// Output colors, with red changing the fastest and blue the slowest
+    entries: Vec<FloatColor>,
+}
+
+impl Lut3d {
+    /// A table mapping every color to itself
+    pub fn identity(size: usize) -> Self {
+        let size = size.max(2);
+        let step = 1.0 / (size - 1) as f32;
+        let mut entries = Vec::with_capacity(size * size * size);
+
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    entries.push(FloatColor::new(
+                        r as f32 * step,
+                        g as f32 * step,
+                        b as f32 * step,
+                    ));
+                }
+            }
+        }
+
+        Self {
+            size,
+            domain_min: FloatColor::BLACK,
+            domain_max: FloatColor::WHITE,
+            entries,
+        }
+    }
+
+    /// Reads a table from an Adobe/Resolve `.cube` file
+    pub fn from_cube(path: &Path) -> Result<Self, LutError<'_>> {
+        let file = File::open(path).map_err(|_| LutError {
+            path: path.to_string_lossy(),
+            line: None,
+            reason: LutErrorReason::OpenError,
+        })?;
+
+        read_cube_lines(BufReader::new(file)).map_err(|(line, reason)| LutError {
+            path: path.to_string_lossy(),
+            line,
+            reason,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Looks up a color with channels from 0 to 1
+    pub fn sample(&self, color: FloatColor) -> FloatColor {
+        let last = (self.size - 1) as f32;
+        let position = |value: f32, min: f32, max: f32| {
+            let scaled = ((value - min) / (max - min)).clamp(0.0, 1.0) * last;
+            let index = (scaled as usize).min(self.size - 2);
+
+            (index, scaled - index as f32)
+        };
+
+        let (r, r_t) = position(color.r, self.domain_min.r, self.domain_max.r);
+        let (g, g_t) = position(color.g, self.domain_min.g, self.domain_max.g);
+        let (b, b_t) = position(color.b, self.domain_min.b, self.domain_max.b);
+
+        let entry =
+            |r: usize, g: usize, b: usize| self.entries[(b * self.size + g) * self.size + r];
+        let lerp = |a: FloatColor, b: FloatColor, t: f32| a + (b - a) * t;
+
+        let lerp_red = |g: usize, b: usize| lerp(entry(r, g, b), entry(r + 1, g, b), r_t);
+        let lerp_green = |b: usize| lerp(lerp_red(g, b), lerp_red(g + 1, b), g_t);
+
+        lerp(lerp_green(b), lerp_green(b + 1), b_t)
+    }
+}
+
+/// Why a LUT file could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LutErrorReason {
+    OpenError,
+    ReadError,
+    InvalidSize,
+    InvalidDomain,
+    InvalidEntry,
+    /// Only 3D tables are supported
+    Unsupported1d,
+    MissingSize,
+    WrongEntryCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl std::fmt::Display for LutErrorReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OpenError => write!(f, "could not open file for reading"),
+            Self::ReadError => write!(f, "could not read line"),
+            Self::InvalidSize => write!(f, "invalid LUT size"),
+            Self::InvalidDomain => write!(f, "invalid LUT domain"),
+            Self::InvalidEntry => write!(f, "invalid LUT entry"),
+            Self::Unsupported1d => write!(f, "1D LUTs are not supported"),
+            Self::MissingSize => write!(f, "LUT_3D_SIZE is missing"),
+            Self::WrongEntryCount { expected, found } => {
+                write!(f, "expected {expected} LUT entries, found {found}")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LutError<'a> {
+    pub path: Cow<'a, str>,
+    /// Line of the file the error was found on, starting at 1
+    pub line: Option<usize>,
+    pub reason: LutErrorReason,
+}
+
+impl std::fmt::Display for LutError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {line}): {}", self.path, self.reason),
+            None => write!(f, "{}: {}", self.path, self.reason),
+        }
+    }
+}
+
+fn read_cube_lines(reader: impl BufRead) -> Result<Lut3d, (Option<usize>, LutErrorReason)> {
+    let mut size = None;
+    let mut domain_min = FloatColor::BLACK;
+    let mut domain_max = FloatColor::WHITE;
+    let mut entries = Vec::new();
+
+    for (line_index, potential_line) in reader.lines().enumerate() {
+        let line_number = Some(line_index + 1);
+        let line = potential_line.map_err(|_| (line_number, LutErrorReason::ReadError))?;
+
+        let Some(keyword) = line.split_ascii_whitespace().next() else {
+            continue;
+        };
+
+        match keyword {
+            _ if keyword.starts_with('#') => {}
+            "TITLE" => {}
+            "LUT_1D_SIZE" => return Err((line_number, LutErrorReason::Unsupported1d)),
+            "LUT_3D_SIZE" => {
+                let lut_size = line
+                    .split_ascii_whitespace()
+                    .nth(1)
+                    .and_then(|size| size.parse::<usize>().ok())
+                    .filter(|size| (2..=MAX_LUT_SIZE).contains(size))
+                    .ok_or((line_number, LutErrorReason::InvalidSize))?;
+
+                size = Some(lut_size);
+                entries.reserve(lut_size * lut_size * lut_size);
+            }
+            "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                let domain = read_numbers::<3, 3>(&line)
+                    .ok_or((line_number, LutErrorReason::InvalidDomain))?;
+                let domain = FloatColor::new(domain[0], domain[1], domain[2]);
+
+                if keyword == "DOMAIN_MIN" {
+                    domain_min = domain;
+                } else {
+                    domain_max = domain;
+                }
+            }
+            // Resolve's input range, which applies to every channel: <min> <max>
+            "LUT_3D_INPUT_RANGE" => {
+                let range = read_numbers::<2, 2>(&line)
+                    .ok_or((line_number, LutErrorReason::InvalidDomain))?;
+
+                domain_min = FloatColor::splat(range[0]);
+                domain_max = FloatColor::splat(range[1]);
+            }
+            // Entries start with a number, so any other keyword is one we don't need
+            _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+            _ => {
+                // Every other line should be an entry, with the output color's channels:
+                // <r> <g> <b>
+                let mut channels = line.split_ascii_whitespace().map(str::parse::<f32>);
+
+                let entry = match (channels.next(), channels.next(), channels.next()) {
+                    (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => FloatColor::new(r, g, b),
+                    _ => return Err((line_number, LutErrorReason::InvalidEntry)),
+                };
+                entries.push(entry);
+            }
+        }
+    }
+
+    let size = size.ok_or((None, LutErrorReason::MissingSize))?;
+
+    let expected = size * size * size;
+    if entries.len() != expected {
+        return Err((
+            None,
+            LutErrorReason::WrongEntryCount {
+                expected,
+                found: entries.len(),
+            },
+        ));
+    }
+
+    if domain_min.r >= domain_max.r || domain_min.g >= domain_max.g || domain_min.b >= domain_max.b
+    {
+        return Err((None, LutErrorReason::InvalidDomain));
+    }
+
+    Ok(Lut3d {
+        size,
+        domain_min,
+        domain_max,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_files_can_be_read() {
+        let lut = read_cube_lines(
+            "# Swaps red and blue\n\
+             TITLE \"swap\"\n\
+             LUT_3D_SIZE 2\n\
+             LUT_3D_INPUT_RANGE 0.0 2.0\n\
+             LUT_IN_VIDEO_RANGE\n\
+             \n\
+             0 0 0\n0 0 1\n0 1 0\n0 1 1\n\
+             1 0 0\n1 0 1\n1 1 0\n1 1 1\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(lut.size(), 2);
+        assert_eq!(
+            lut.sample(FloatColor::new(2.0, 1.0, 0.0)),
+            FloatColor::new(0.0, 0.5, 1.0)
+        );
+
+        assert_eq!(
+            read_cube_lines("LUT_3D_SIZE 2\n0 0 0\n0 oops 1\n".as_bytes()),
+            Err((Some(3), LutErrorReason::InvalidEntry))
+        );
+        assert_eq!(
+            read_cube_lines("LUT_3D_SIZE 2\n0 0 0\n".as_bytes()),
+            Err((
+                None,
+                LutErrorReason::WrongEntryCount {
+                    expected: 8,
+                    found: 1
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn effects_run_in_order() {
+        let mut color_buffer = ColorBuffer::new(4, 4);
+        let depth_buffer = DepthBuffer::new(4, 4);
+        color_buffer.clear(Color::new(200, 40, 40));
+
+        let effects: Vec<Box<dyn PostEffect>> = vec![
+            Box::new(ColorGrading {
+                lut: Lut3d::identity(17),
+                intensity: 1.0,
+            }),
+            Box::new(Grayscale),
+            Box::new(Sharpen { amount: 1.0 }),
+        ];
+        for effect in effects.iter() {
+            effect.apply(&mut color_buffer, &depth_buffer);
+        }
+
+        // The identity grade and sharpening a flat frame leave just the gray
+        let gray = Color::from(color_buffer.buffer()[5]);
+        assert_eq!((gray.r, gray.g, gray.b), (104, 104, 104));
+        assert!(color_buffer
+            .buffer()
+            .iter()
+            .all(|&pixel| pixel == color_buffer.buffer()[0]));
+    }
+}
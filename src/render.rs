@@ -2,15 +2,22 @@ use tinyvec::ArrayVec;
 
 use crate::{
     camera::Camera,
-    color::Color,
+    color::{Color, FloatColor},
     color_buffer::ColorBuffer,
     depth_buffer::DepthBuffer,
+    drawing::PixelState,
+    fxaa::FxaaQuality,
+    hdr::HdrSettings,
+    lighting::{Lighting, PixelLighting},
+    material::Material,
     matrix::Mat4,
     mesh::Mesh,
     plane::Plane,
-    polygon::{Polygon, PolygonVertex},
+    polygon::{ClipVertex, Polygon, PolygonVertex, MAX_TRIANGLES},
+    shadow::ShadowSettings,
     texture::Texture,
-    triangle::Triangle,
+    tile::{available_threads, draw_tiled},
+    triangle::{Triangle, VertexData},
     vector::{Vec3, Vec4},
 };
 
@@ -25,18 +32,77 @@ pub enum RenderMode {
     Depth,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShadingMode {
+    /// Lighting is computed once per face from the face normal
+    Flat,
+    /// Lighting is computed per vertex from the vertex normals and interpolated across the face
+    Gouraud,
+    /// Normals and positions are interpolated across the face and lit per pixel with Blinn-Phong
+    /// specular highlights
+    Phong,
+}
+
 #[derive(Clone, Copy)]
 pub struct RenderSettings {
     pub render_mode: RenderMode,
     pub backface_cull: bool,
     pub shaded: bool,
+    pub shading_mode: ShadingMode,
     pub translate: bool,
     pub rotate: bool,
     pub rotation: Vec3,
     pub scale: bool,
     pub flip_uvs_vertically: bool,
+    /// Sample textures from their mip chains with trilinear filtering, instead of always
+    /// sampling the full size texture
+    pub mipmapping: bool,
+    /// Number of threads to rasterize with. With more than one thread the screen is split
+    /// into tiles that are drawn in parallel.
+    pub threads: usize,
+    /// Evaluate triangle edges for several pixels at once with SIMD instructions. Output is
+    /// identical either way.
+    pub simd_rasterization: bool,
+    /// Light and blend in linear space, with textures and colors decoded from sRGB and the
+    /// result encoded back to sRGB when it's written. When turned off, the 8-bit sRGB values
+    /// are lit and blended directly.
+    pub linear_lighting: bool,
     pub render_width: u16,
     pub render_height: u16,
+    pub shadows: ShadowSettings,
+    /// Exposure and tone mapping used to resolve HDR color buffers
+    pub hdr: HdrSettings,
+    /// Run the scene's post effects over each finished frame
+    pub post_processing: bool,
+    /// Smooth jagged edges with FXAA at this quality, before the scene's post effects are run
+    pub fxaa: Option<FxaaQuality>,
+}
+
+impl RenderSettings {
+    /// Settings used by the viewer on startup: textured, lit and slowly rotating
+    pub fn new(render_width: u16, render_height: u16) -> Self {
+        Self {
+            render_mode: RenderMode::Textured,
+            backface_cull: true,
+            shaded: true,
+            shading_mode: ShadingMode::Phong,
+            translate: false,
+            rotate: true,
+            rotation: Vec3::new(0.0, 0.01, 0.0),
+            scale: false,
+            flip_uvs_vertically: false,
+            mipmapping: true,
+            threads: available_threads(),
+            simd_rasterization: true,
+            linear_lighting: true,
+            render_width,
+            render_height,
+            shadows: ShadowSettings::default(),
+            hdr: HdrSettings::default(),
+            post_processing: true,
+            fxaa: None,
+        }
+    }
 }
 
 pub fn prepare_triangles(
@@ -45,21 +111,12 @@ pub fn prepare_triangles(
     mesh: &Mesh,
     camera: &Camera,
     settings: &RenderSettings,
+    lighting: &Lighting<'_>,
 ) {
     triangles_to_render.clear();
 
-    let scale_matrix = Mat4::scale(mesh.scale.x, mesh.scale.y, mesh.scale.z);
-    let translation_matrix =
-        Mat4::translation(mesh.translation.x, mesh.translation.y, mesh.translation.z);
-    let rotation_x_matrix = Mat4::rotation_x(mesh.rotation.x);
-    let rotation_y_matrix = Mat4::rotation_y(mesh.rotation.y);
-    let rotation_z_matrix = Mat4::rotation_z(mesh.rotation.z);
-
-    let world_matrix = translation_matrix
-        * rotation_x_matrix
-        * rotation_y_matrix
-        * rotation_z_matrix
-        * scale_matrix;
+    let world_matrix = mesh.world_matrix();
+    let normal_matrix = mesh.normal_matrix();
     let camera_matrix = camera.view_matrix();
 
     for face in mesh.faces.iter() {
@@ -89,20 +146,56 @@ pub fn prepare_triangles(
         }
 
         // Lighting
-        let light_direction = Vec3::new(0.0, 0.0, 1.0).normalized();
-        let percent_lit = normal.dot(light_direction) * -0.5 + 0.5;
-        let triangle_color = if settings.shaded
-            && (settings.render_mode == RenderMode::WireframeTextured
-                || settings.render_mode == RenderMode::Textured)
-        {
-            Color::new(255, 255, 255) * percent_lit
-        } else if !settings.shaded
-            && (settings.render_mode == RenderMode::WireframeTextured
-                || settings.render_mode == RenderMode::Textured)
+        let vertex_normals = match face.normals {
+            Some(normal_indices) if settings.shading_mode != ShadingMode::Flat => normal_indices
+                .map(|i| {
+                    let vertex_normal = mesh.vertex_normals[i as usize];
+                    let world_normal = normal_matrix
+                        * Vec4::new(vertex_normal.x, vertex_normal.y, vertex_normal.z, 0.0);
+
+                    Vec3::from(world_normal).normalized_or_zero()
+                }),
+            // Faces without vertex normals are lit using the face normal
+            _ => [normal; 3],
+        };
+
+        // Tangents lie along the surface, so they're transformed like positions rather than
+        // like normals
+        let vertex_tangents = match face.tangents {
+            Some(tangent_indices) => tangent_indices.map(|i| {
+                let tangent = mesh.vertex_tangents[i as usize];
+                let to_world = |direction: Vec3| {
+                    Vec3::from(world_matrix * Vec4::new(direction.x, direction.y, direction.z, 0.0))
+                };
+
+                (to_world(tangent.tangent), to_world(tangent.bitangent))
+            }),
+            None => [(Vec3::default(), Vec3::default()); 3],
+        };
+
+        let vertex_light = match settings.shading_mode {
+            _ if !settings.shaded => [FloatColor::WHITE; 3],
+            ShadingMode::Flat => {
+                let centroid = (Vec3::from(world_transformed_vertices[0])
+                    + Vec3::from(world_transformed_vertices[1])
+                    + Vec3::from(world_transformed_vertices[2]))
+                    / 3.0;
+
+                [lighting.diffuse(normal, centroid); 3]
+            }
+            ShadingMode::Gouraud => [0, 1, 2].map(|i| {
+                lighting.diffuse(vertex_normals[i], Vec3::from(world_transformed_vertices[i]))
+            }),
+            // Phong shading lights each pixel when drawing instead
+            ShadingMode::Phong => [FloatColor::WHITE; 3],
+        };
+
+        // Textures are only tinted by the color of faces with a material
+        let triangle_color = if (settings.render_mode == RenderMode::WireframeTextured
+            || settings.render_mode == RenderMode::Textured)
+            && face.material.is_none()
         {
             Color::new(255, 255, 255)
-        } else if settings.shaded {
-            face.color * percent_lit
         } else {
             face.color
         };
@@ -115,119 +208,212 @@ pub fn prepare_triangles(
         let projected_vertices = camera_transformed_vertices.map(|v| projection_matrix * v);
 
         // Clip
-        let mut polygon_verts = ArrayVec::new();
-        polygon_verts.push(PolygonVertex {
-            pos: projected_vertices[0].into(),
-            uv: mesh.vertex_uvs[face.a_uv as usize],
-        });
-        polygon_verts.push(PolygonVertex {
-            pos: projected_vertices[1].into(),
-            uv: mesh.vertex_uvs[face.b_uv as usize],
-        });
-        polygon_verts.push(PolygonVertex {
-            pos: projected_vertices[2].into(),
-            uv: mesh.vertex_uvs[face.c_uv as usize],
-        });
+        let uv_indices = [face.a_uv, face.b_uv, face.c_uv];
+        let vertex_indices = [face.a, face.b, face.c];
+        let polygon_verts = [0, 1, 2]
+            .map(|i| PolygonVertex {
+                pos: projected_vertices[i],
+                uv: mesh.vertex_uvs[uv_indices[i] as usize],
+                attributes: VertexData {
+                    light: vertex_light[i],
+                    normal: vertex_normals[i],
+                    world_pos: Vec3::from(world_transformed_vertices[i]),
+                    tangent: vertex_tangents[i].0,
+                    bitangent: vertex_tangents[i].1,
+                    color: mesh.vertex_color(vertex_indices[i] as usize),
+                }
+                .into(),
+            })
+            .into_iter()
+            .collect();
 
         let polygon = Polygon::new(polygon_verts);
 
-        let polygon = Plane::Right.clip_polygon(&polygon);
-        let polygon = Plane::Left.clip_polygon(&polygon);
-        let polygon = Plane::Top.clip_polygon(&polygon);
-        let polygon = Plane::Bottom.clip_polygon(&polygon);
-        let polygon = Plane::Far.clip_polygon(&polygon);
-        let polygon = Plane::Near.clip_polygon(&polygon);
-        let polygon = Plane::W.clip_polygon(&polygon);
-
-        let clipped_triangles = polygon.triangulate();
-
-        // Finish projection
-        for triangle in clipped_triangles {
-            let projected_vertices = triangle.map(|vertex| {
-                let mut projected = Vec4::new(
-                    vertex.pos.x / vertex.pos.w,
-                    vertex.pos.y / vertex.pos.w,
-                    vertex.pos.z / vertex.pos.w,
-                    vertex.pos.w,
-                );
-
-                // Scale and translate into view
-                projected.x = (projected.x + 1.0) * (settings.render_width as f32 - 1.0) / 2.0;
-                projected.y = (projected.y - 1.0) * (settings.render_height as f32 - 1.0) / -2.0;
-
-                projected
-            });
-
-            let triangle = Triangle::new(
-                projected_vertices[0],
-                projected_vertices[1],
-                projected_vertices[2],
-                triangle[0].uv,
-                triangle[1].uv,
-                triangle[2].uv,
+        for triangle in clip_and_project(&polygon, settings.render_width, settings.render_height) {
+            let mut triangle = Triangle::new(
+                triangle.map(|vertex| vertex.pos),
+                triangle.map(|vertex| vertex.uv),
+                triangle.map(|vertex| vertex.attributes),
                 triangle_color,
             );
+            triangle.material = face.material;
 
             triangles_to_render.push(triangle);
         }
     }
 }
 
+/// Clip a polygon in clip space against each plane of the view frustum
+pub(crate) fn clip_to_frustum<V: ClipVertex>(polygon: &Polygon<V>) -> Polygon<V> {
+    let polygon = Plane::Right.clip_polygon(polygon);
+    let polygon = Plane::Left.clip_polygon(&polygon);
+    let polygon = Plane::Top.clip_polygon(&polygon);
+    let polygon = Plane::Bottom.clip_polygon(&polygon);
+    let polygon = Plane::Far.clip_polygon(&polygon);
+    let polygon = Plane::Near.clip_polygon(&polygon);
+    Plane::W.clip_polygon(&polygon)
+}
+
+/// Clip a polygon in clip space against the view frustum, then finish projecting the resulting
+/// triangles into screen space. The projected positions hold the screen x and y coordinates, the
+/// normalized depth in z, and the original w for perspective correct interpolation.
+pub(crate) fn clip_and_project<V: ClipVertex>(
+    polygon: &Polygon<V>,
+    width: u16,
+    height: u16,
+) -> ArrayVec<[[V; 3]; MAX_TRIANGLES]> {
+    let mut clipped_triangles = clip_to_frustum(polygon).triangulate();
+
+    // Finish projection
+    for triangle in clipped_triangles.iter_mut() {
+        for vertex in triangle.iter_mut() {
+            let pos = vertex.position();
+            let mut projected = Vec4::new(pos.x / pos.w, pos.y / pos.w, pos.z / pos.w, pos.w);
+
+            // Scale and translate into view
+            projected.x = (projected.x + 1.0) * (width as f32 - 1.0) / 2.0;
+            projected.y = (projected.y - 1.0) * (height as f32 - 1.0) / -2.0;
+
+            vertex.set_position(projected);
+        }
+    }
+
+    clipped_triangles
+}
+
+/// Textures a group of triangles is drawn with
+#[derive(Clone, Copy)]
+pub struct SurfaceTextures<'a> {
+    pub texture: &'a Texture,
+    /// Tangent space normal map, used when lighting per pixel
+    pub normal_map: Option<&'a Texture>,
+}
+
 pub fn render(
     color_buffer: &mut ColorBuffer,
     depth_buffer: &mut DepthBuffer,
     triangles_to_render: &[Triangle],
     settings: &RenderSettings,
-    texture: &Texture,
+    lighting: &Lighting<'_>,
+    material: &Material,
+    textures: SurfaceTextures<'_>,
 ) {
-    for triangle in triangles_to_render.iter() {
-        for point in triangle.points {
-            if point.x == f32::NEG_INFINITY
-                || point.x == f32::INFINITY
-                || point.y == f32::NEG_INFINITY
-                || point.y == f32::INFINITY
-            {
-                continue;
-            }
+    let SurfaceTextures {
+        texture,
+        normal_map,
+    } = textures;
 
-            if matches!(settings.render_mode, RenderMode::WireframeVertex) {
-                color_buffer.draw_rect(
-                    point.x as u16,
-                    point.y as u16,
-                    2,
-                    2,
-                    Color::new(0, 0xFF, 0),
-                );
-            }
-        }
+    let pixel_lighting = if settings.shaded && settings.shading_mode == ShadingMode::Phong {
+        Some(PixelLighting {
+            lighting,
+            material,
+            normal_map,
+            flip_v: settings.flip_uvs_vertically,
+        })
+    } else {
+        None
+    };
 
-        match settings.render_mode {
-            RenderMode::Wireframe | RenderMode::WireframeVertex => {
-                color_buffer.draw_triangle(triangle, Color::new(0, 0xFF, 0));
-            }
-            RenderMode::Filled | RenderMode::Depth => {
-                color_buffer.draw_filled_triangle(triangle, triangle.color, depth_buffer);
-            }
-            RenderMode::WireframeFilled => {
-                color_buffer.draw_triangle(triangle, Color::new(0xFF, 0, 0));
-                color_buffer.draw_filled_triangle(triangle, triangle.color, depth_buffer);
-            }
-            RenderMode::Textured => color_buffer.draw_textured_triangle(
-                triangle,
-                &texture,
-                depth_buffer,
-                settings.flip_uvs_vertically,
-            ),
-            RenderMode::WireframeTextured => {
-                color_buffer.draw_triangle(triangle, Color::new(0xFF, 0, 0));
-                color_buffer.draw_textured_triangle(
+    let pixel_state = PixelState {
+        pixel_lighting,
+        blend_mode: material.blend_mode,
+        opacity: material.opacity,
+        alpha_cutoff: material.alpha_cutoff,
+        simd: settings.simd_rasterization,
+        linear: settings.linear_lighting,
+    };
+
+    let mut triangles: Vec<&Triangle> = triangles_to_render.iter().collect();
+
+    // Blended triangles are drawn back to front, so the ones behind show through
+    if material.is_transparent() {
+        triangles.sort_by(|a, b| view_depth(b).total_cmp(&view_depth(a)));
+    }
+
+    let draw_triangle =
+        |color_buffer: &mut ColorBuffer, depth_buffer: &mut DepthBuffer, triangle: &Triangle| {
+            match settings.render_mode {
+                RenderMode::Wireframe | RenderMode::WireframeVertex => {
+                    color_buffer.draw_triangle(triangle, Color::new(0, 0xFF, 0));
+                }
+                RenderMode::Filled | RenderMode::Depth => {
+                    color_buffer.draw_filled_triangle(
+                        triangle,
+                        triangle.color,
+                        depth_buffer,
+                        pixel_state,
+                    );
+                }
+                RenderMode::WireframeFilled => {
+                    color_buffer.draw_triangle(triangle, Color::new(0xFF, 0, 0));
+                    color_buffer.draw_filled_triangle(
+                        triangle,
+                        triangle.color,
+                        depth_buffer,
+                        pixel_state,
+                    );
+                }
+                RenderMode::Textured => color_buffer.draw_textured_triangle(
                     triangle,
-                    &texture,
+                    texture,
                     depth_buffer,
                     settings.flip_uvs_vertically,
-                );
-            }
+                    settings.mipmapping,
+                    pixel_state,
+                ),
+                RenderMode::WireframeTextured => {
+                    color_buffer.draw_triangle(triangle, Color::new(0xFF, 0, 0));
+                    color_buffer.draw_textured_triangle(
+                        triangle,
+                        texture,
+                        depth_buffer,
+                        settings.flip_uvs_vertically,
+                        settings.mipmapping,
+                        pixel_state,
+                    );
+                }
+            };
         };
+
+    // Lines aren't clipped to tiles, so wireframes are always drawn on a single thread
+    let tiled = settings.threads > 1
+        && matches!(
+            settings.render_mode,
+            RenderMode::Filled | RenderMode::Depth | RenderMode::Textured
+        );
+
+    if tiled {
+        draw_tiled(
+            color_buffer,
+            depth_buffer,
+            &triangles,
+            settings.threads,
+            draw_triangle,
+        );
+    } else {
+        for triangle in triangles {
+            for point in triangle.points {
+                if point.x == f32::NEG_INFINITY
+                    || point.x == f32::INFINITY
+                    || point.y == f32::NEG_INFINITY
+                    || point.y == f32::INFINITY
+                {
+                    continue;
+                }
+
+                if matches!(settings.render_mode, RenderMode::WireframeVertex) {
+                    color_buffer.draw_rect(
+                        point.x as u16,
+                        point.y as u16,
+                        2,
+                        2,
+                        Color::new(0, 0xFF, 0),
+                    );
+                }
+            }
+
+            draw_triangle(color_buffer, depth_buffer, triangle);
+        }
     }
 
     if settings.render_mode == RenderMode::Depth {
@@ -239,3 +425,9 @@ pub fn render(
         }
     }
 }
+
+/// Average distance of a triangle's points from the camera
+pub(crate) fn view_depth(triangle: &Triangle) -> f32 {
+    // After projection w holds each point's depth in view space
+    triangle.points.iter().map(|point| point.w).sum::<f32>() / 3.0
+}
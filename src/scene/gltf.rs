@@ -0,0 +1,397 @@
+use std::{borrow::Cow, fs, path::Path};
+
+use base64::Engine;
+use gltf::{
+    buffer, image,
+    material::AlphaMode,
+    mesh::Mode,
+    texture::{MagFilter, WrappingMode},
+    Gltf, Node, Primitive,
+};
+
+use crate::{
+    camera::Camera,
+    color::{Color, LinearColor},
+    material::{BlendMode, Material},
+    matrix::Mat4,
+    mesh::Mesh,
+    texture::{Filter, Sampler, Tex2, Texture, TextureError, WrapMode},
+    triangle::Face,
+    vector::{Vec3, Vec4},
+};
+
+use super::{Object, Scene};
+
+pub enum GltfError<'a> {
+    ReadError(Cow<'a, str>),
+    InvalidGltf(gltf::Error),
+    /// The buffer with this index is missing or too short
+    BufferError(usize),
+}
+
+impl std::fmt::Display for GltfError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadError(path) => {
+                write!(f, "could not open glTF file for reading at {path}")
+            }
+            Self::InvalidGltf(err) => {
+                write!(f, "{err}")
+            }
+            Self::BufferError(index) => {
+                write!(f, "could not load buffer {index}")
+            }
+        }
+    }
+}
+
+/// Reads a scene from a glTF (.gltf) or binary glTF (.glb) file
+pub fn read_gltf(path: &Path, camera: Camera) -> Result<Scene, GltfError<'_>> {
+    let mut scene = Scene::new(camera);
+
+    for object in read_gltf_objects(path)? {
+        scene.add_object(object);
+    }
+
+    Ok(scene)
+}
+
+/// Reads the meshes of a glTF file's default scene, with one object for each primitive. Node
+/// transforms are baked into the vertices, apart from the translation which becomes the
+/// mesh's own.
+pub fn read_gltf_objects(path: &Path) -> Result<Vec<Object>, GltfError<'_>> {
+    let gltf = Gltf::open(path).map_err(|err| match err {
+        gltf::Error::Io(_) => GltfError::ReadError(path.to_string_lossy()),
+        err => GltfError::InvalidGltf(err),
+    })?;
+
+    read_objects(gltf, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Reads the objects of a parsed glTF file, with external files relative to `directory`
+fn read_objects(gltf: Gltf, directory: &Path) -> Result<Vec<Object>, GltfError<'static>> {
+    let buffers = gltf
+        .document
+        .buffers()
+        .map(|buffer| {
+            load_buffer(&buffer, gltf.blob.as_deref(), directory)
+                .ok_or(GltfError::BufferError(buffer.index()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Textures are decoded once, since any number of primitives can use them
+    let textures: Vec<Texture> = gltf
+        .document
+        .textures()
+        .map(|texture| {
+            let mut decoded = load_image(&texture.source(), &buffers, directory)
+                .ok_or(TextureError::DecodeError)
+                .and_then(|png| Texture::from_png_reader(png.as_slice()))
+                .unwrap_or_else(|err| {
+                    eprintln!("Error reading texture: {err}");
+                    Texture::from_color(1, 1, Color::new(0xFF, 0x00, 0xFF))
+                });
+            decoded.sampler = convert_sampler(&texture.sampler());
+            decoded
+        })
+        .collect();
+
+    let mut objects = Vec::new();
+
+    if let Some(scene) = gltf
+        .document
+        .default_scene()
+        .or_else(|| gltf.document.scenes().next())
+    {
+        for node in scene.nodes() {
+            read_node(&node, Mat4::IDENTITY, &buffers, &textures, &mut objects);
+        }
+    }
+
+    Ok(objects)
+}
+
+fn load_buffer(buffer: &gltf::Buffer, blob: Option<&[u8]>, directory: &Path) -> Option<Vec<u8>> {
+    let data = match buffer.source() {
+        buffer::Source::Bin => blob?.to_vec(),
+        buffer::Source::Uri(uri) => load_uri(uri, directory)?,
+    };
+
+    // The binary chunk of a .glb file may be padded past the buffer's length
+    (data.len() >= buffer.length()).then_some(data)
+}
+
+fn load_image(image: &gltf::Image, buffers: &[Vec<u8>], directory: &Path) -> Option<Vec<u8>> {
+    match image.source() {
+        image::Source::View { view, .. } => buffers
+            .get(view.buffer().index())?
+            .get(view.offset()..view.offset() + view.length())
+            .map(<[u8]>::to_vec),
+        image::Source::Uri { uri, .. } => load_uri(uri, directory),
+    }
+}
+
+/// Reads the contents of a base64 data URI, or of a file relative to the glTF file
+fn load_uri(uri: &str, directory: &Path) -> Option<Vec<u8>> {
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let (_, encoded) = data.split_once(";base64,")?;
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .ok()
+        }
+        None => fs::read(directory.join(uri)).ok(),
+    }
+}
+
+/// glTF leaves filtering up to the renderer when a sampler doesn't set it, and most models
+/// expect their textures to be smoothed
+fn convert_sampler(sampler: &gltf::texture::Sampler) -> Sampler {
+    let filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => Filter::Nearest,
+        _ => Filter::Bilinear,
+    };
+
+    let wrap_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => WrapMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => WrapMode::MirroredRepeat,
+        WrappingMode::Repeat => WrapMode::Repeat,
+    };
+
+    Sampler::new(
+        filter,
+        wrap_mode(sampler.wrap_s()),
+        wrap_mode(sampler.wrap_t()),
+    )
+}
+
+fn read_node(
+    node: &Node,
+    parent_transform: Mat4,
+    buffers: &[Vec<u8>],
+    textures: &[Texture],
+    objects: &mut Vec<Object>,
+) {
+    let [x, y, z, w] = node
+        .transform()
+        .matrix()
+        .map(|[x, y, z, w]| Vec4::new(x, y, z, w));
+    let transform = parent_transform * Mat4::new(x, y, z, w);
+
+    if let Some(mesh) = node.mesh() {
+        objects.extend(
+            mesh.primitives()
+                .filter_map(|primitive| read_primitive(&primitive, transform, buffers, textures)),
+        );
+    }
+
+    for child in node.children() {
+        read_node(&child, transform, buffers, textures, objects);
+    }
+}
+
+fn read_primitive(
+    primitive: &Primitive,
+    transform: Mat4,
+    buffers: &[Vec<u8>],
+    textures: &[Texture],
+) -> Option<Object> {
+    // Points and lines have no surface to draw
+    if primitive.mode() != Mode::Triangles {
+        return None;
+    }
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let gltf_material = primitive.material();
+    let pbr = gltf_material.pbr_metallic_roughness();
+    let base_color_texture = pbr.base_color_texture();
+
+    let translation = Vec3::from(transform * Vec4::new(0.0, 0.0, 0.0, 1.0));
+    let [x_axis, y_axis, z_axis] = [
+        Vec4::new(1.0, 0.0, 0.0, 0.0),
+        Vec4::new(0.0, 1.0, 0.0, 0.0),
+        Vec4::new(0.0, 0.0, 1.0, 0.0),
+    ]
+    .map(|axis| Vec3::from(transform * axis));
+
+    // Normals are transformed by the cofactor matrix, which is the inverse transpose scaled
+    // by the determinant. Mirroring transforms have a negative determinant, which flips the
+    // normals and the winding of every face.
+    let determinant = x_axis.dot(y_axis.cross(z_axis));
+    let mirrored = determinant < 0.0;
+    let normal_axes = [
+        y_axis.cross(z_axis),
+        z_axis.cross(x_axis),
+        x_axis.cross(y_axis),
+    ]
+    .map(|axis| if mirrored { axis * -1.0 } else { axis });
+
+    let vertices: Vec<Vec3> = reader
+        .read_positions()?
+        .map(|[x, y, z]| Vec3::from(transform * Vec4::new(x, y, z, 1.0)) - translation)
+        .collect();
+    let normals: Vec<Vec3> = reader
+        .read_normals()
+        .map(|normals| {
+            normals
+                .map(|[x, y, z]| {
+                    (normal_axes[0] * x + normal_axes[1] * y + normal_axes[2] * z)
+                        .normalized_or_zero()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut uvs: Vec<Tex2> = reader
+        .read_tex_coords(
+            base_color_texture
+                .as_ref()
+                .map_or(0, |info| info.tex_coord()),
+        )
+        .map(|uvs| uvs.into_f32().map(|[u, v]| Tex2::new(u, v)).collect())
+        .unwrap_or_default();
+
+    // The base color is carried by the vertex colors, so it tints textured and filled
+    // triangles alike, along with the alpha of blended materials. glTF colors are linear, so
+    // they're encoded as sRGB like the rest of the mesh's colors.
+    let [r, g, b, a] = pbr.base_color_factor();
+    let base_color = LinearColor::new(r, g, b, a);
+    let vertex_colors: Vec<Color> = match reader.read_colors(0) {
+        Some(colors) => colors
+            .into_rgba_f32()
+            .map(|[r, g, b, a]| (LinearColor::new(r, g, b, a) * base_color).to_srgb())
+            .collect(),
+        None if pbr.base_color_factor() != [1.0; 4] => {
+            vec![base_color.to_srgb(); vertices.len()]
+        }
+        None => Vec::new(),
+    };
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+
+    // Vertices without texture coordinates all use a single default UV
+    let has_uvs = uvs.len() == vertices.len();
+    if !has_uvs {
+        uvs = vec![Tex2::default()];
+    }
+    let uv_index = |index: u32| if has_uvs { index } else { 0 };
+
+    let faces = indices
+        .chunks_exact(3)
+        .filter(|face| face.iter().all(|&index| (index as usize) < vertices.len()))
+        .map(|face| {
+            let [a, b, c] = if mirrored {
+                [face[0], face[2], face[1]]
+            } else {
+                [face[0], face[1], face[2]]
+            };
+            let face = Face::new(
+                a,
+                b,
+                c,
+                uv_index(a),
+                uv_index(b),
+                uv_index(c),
+                Color::new(0xFF, 0xFF, 0xFF),
+            );
+
+            if normals.len() == vertices.len() {
+                face.with_normals(a, b, c)
+            } else {
+                face
+            }
+        })
+        .collect();
+
+    let mut material = Material {
+        // Smoother surfaces have stronger highlights
+        specular: 1.0 - pbr.roughness_factor(),
+        ..Material::default()
+    };
+    match gltf_material.alpha_mode() {
+        AlphaMode::Opaque => {}
+        AlphaMode::Mask => material.alpha_cutoff = gltf_material.alpha_cutoff().unwrap_or(0.5),
+        AlphaMode::Blend => material.blend_mode = BlendMode::SourceOver,
+    }
+
+    // Primitives without a base color texture are drawn with the base color alone
+    let texture = base_color_texture
+        .and_then(|info| textures.get(info.texture().index()).cloned())
+        .unwrap_or_else(|| Texture::from_color(1, 1, Color::new(0xFF, 0xFF, 0xFF)));
+
+    let mesh = Mesh::new(
+        vertices,
+        uvs,
+        normals,
+        faces,
+        Vec3::default(),
+        Vec3::splat(1.0),
+        translation,
+    )
+    .with_vertex_colors(vertex_colors);
+
+    Some(Object::new(mesh, texture, material))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dark red, half transparent triangle in a mirrored child node of a translated node
+    const MIRRORED_TRIANGLE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [1.0, 2.0, 3.0], "children": [1] },
+            { "scale": [2.0, 1.0, -1.0], "mesh": 0 }
+        ],
+        "meshes": [{
+            "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1 }, "material": 0 }]
+        }],
+        "materials": [{
+            "pbrMetallicRoughness": { "baseColorFactor": [0.5, 0.0, 0.0, 0.5] },
+            "alphaMode": "BLEND"
+        }],
+        "accessors": [
+            {
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+            },
+            { "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3" }
+        ],
+        "bufferViews": [{ "buffer": 0, "byteLength": 72 }],
+        "buffers": [{
+            "byteLength": 72,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/AAAAAAAAAAAAAIA/"
+        }]
+    }"#;
+
+    #[test]
+    fn node_transforms_and_base_color_are_applied() {
+        let gltf = Gltf::from_slice(MIRRORED_TRIANGLE.as_bytes()).unwrap();
+        let objects = read_objects(gltf, Path::new("")).unwrap_or_else(|err| panic!("{err}"));
+
+        assert_eq!(objects.len(), 1);
+        let mesh = &objects[0].mesh;
+
+        assert_eq!(mesh.translation, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(
+            mesh.vertices,
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0)
+            ]
+        );
+
+        // Mirroring flips the normals and the winding of the face
+        assert_eq!(mesh.vertex_normals, [Vec3::new(0.0, 0.0, -1.0); 3]);
+        let face = &mesh.faces[0];
+        assert_eq!([face.a, face.b, face.c], [0, 2, 1]);
+
+        // The linear base color is encoded as sRGB
+        assert_eq!(mesh.vertex_colors, [Color::rgba(188, 0, 0, 128); 3]);
+        assert_eq!(objects[0].material.blend_mode, BlendMode::SourceOver);
+    }
+}
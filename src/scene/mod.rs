@@ -1,27 +1,222 @@
+#[cfg(feature = "window")]
 use minifb::{Key, KeyRepeat, Window};
 
-use crate::{camera::Camera, mesh::Mesh, texture::Texture, vector::Vec3, RenderSettings};
+use crate::{
+    camera::Camera,
+    color::{Color, FloatColor},
+    color_buffer::ColorBuffer,
+    depth_buffer::DepthBuffer,
+    lighting::{Light, Lighting},
+    material::Material,
+    matrix::Mat4,
+    mesh::Mesh,
+    post::PostEffect,
+    render::{prepare_triangles, render, view_depth, SurfaceTextures},
+    texture::Texture,
+    triangle::Triangle,
+    vector::Vec3,
+    RenderSettings,
+};
 
+pub mod gltf;
 pub mod reader;
 
+#[cfg(feature = "window")]
 const CAMERA_MOVEMENT_SPEED: f32 = 3.0;
+#[cfg(feature = "window")]
 const CAMERA_LOOK_SENSITIVITY: f32 = 0.025;
 
+/// How the faces using one of a mesh's materials are drawn
+pub struct Surface {
+    pub material: Material,
+    pub texture: Texture,
+    /// Normal map read from the material's bump map
+    pub normal_map: Option<Texture>,
+}
+
 pub struct Object {
     pub mesh: Mesh,
     pub texture: Texture,
+    pub material: Material,
+    /// Tangent space normal map for faces without a material, used when lighting per pixel
+    pub normal_map: Option<Texture>,
+    /// One surface for each of the mesh's materials. Faces without a material are drawn
+    /// with the object's texture and material.
+    pub surfaces: Vec<Surface>,
+}
+
+impl Object {
+    /// Creates an object, loading the textures of the mesh's materials
+    pub fn new(mut mesh: Mesh, texture: Texture, material: Material) -> Self {
+        let surfaces: Vec<Surface> = mesh
+            .materials
+            .iter()
+            .map(|mtl_material| {
+                let texture = match &mtl_material.diffuse_map {
+                    Some(path) => Texture::from_png(path).unwrap_or_else(|err| {
+                        eprintln!("Error reading texture: {err}");
+                        Texture::from_color(1, 1, Color::new(0xFF, 0x00, 0xFF))
+                    }),
+                    // Faces are already tinted with the material's diffuse color
+                    None => Texture::from_color(1, 1, Color::new(0xFF, 0xFF, 0xFF)),
+                };
+
+                // Surfaces are still drawn if their normal map can't be read, just without it
+                let normal_map = mtl_material.bump_map.as_ref().and_then(|path| {
+                    Texture::from_png(path)
+                        .map_err(|err| eprintln!("Error reading normal map: {err}"))
+                        .ok()
+                });
+
+                Surface {
+                    material: mtl_material.to_material(material),
+                    texture,
+                    normal_map,
+                }
+            })
+            .collect();
+
+        if surfaces.iter().any(|surface| surface.normal_map.is_some()) {
+            mesh.generate_tangents();
+        }
+
+        Self {
+            mesh,
+            texture,
+            material,
+            normal_map: None,
+            surfaces,
+        }
+    }
+
+    /// Sets the normal map of faces without a material, generating the mesh's tangents if it
+    /// doesn't have them yet
+    pub fn with_normal_map(mut self, normal_map: Texture) -> Self {
+        if self.mesh.vertex_tangents.is_empty() {
+            self.mesh.generate_tangents();
+        }
+
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    /// Material of faces using one of the mesh's materials, or the object's own material for
+    /// faces without one
+    fn face_material(&self, material: Option<u32>) -> &Material {
+        match material {
+            Some(index) => &self.surfaces[index as usize].material,
+            None => &self.material,
+        }
+    }
+
+    /// Material and textures of faces using one of the mesh's materials, or the object's own
+    /// for faces without one
+    fn surface(&self, material: Option<u32>) -> (&Material, SurfaceTextures<'_>) {
+        match material {
+            Some(index) => {
+                let surface = &self.surfaces[index as usize];
+                let textures = SurfaceTextures {
+                    texture: &surface.texture,
+                    normal_map: surface.normal_map.as_ref(),
+                };
+                (&surface.material, textures)
+            }
+            None => {
+                let textures = SurfaceTextures {
+                    texture: &self.texture,
+                    normal_map: self.normal_map.as_ref(),
+                };
+                (&self.material, textures)
+            }
+        }
+    }
+
+    /// Draws the object's opaque triangles prepared by `prepare_triangles`, grouped by
+    /// material. Its transparent triangles are left in `triangles_to_render`, to be drawn by
+    /// `render_transparent` once every opaque surface has been drawn.
+    pub fn render_opaque(
+        &self,
+        color_buffer: &mut ColorBuffer,
+        depth_buffer: &mut DepthBuffer,
+        triangles_to_render: &mut Vec<Triangle>,
+        settings: &RenderSettings,
+        lighting: &Lighting<'_>,
+    ) {
+        let is_transparent =
+            |triangle: &Triangle| self.face_material(triangle.material).is_transparent();
+
+        if !self.surfaces.is_empty() {
+            triangles_to_render
+                .sort_by_key(|triangle| (is_transparent(triangle), triangle.material));
+        }
+        let opaque_triangles =
+            triangles_to_render.partition_point(|triangle| !is_transparent(triangle));
+
+        for triangles in
+            triangles_to_render[..opaque_triangles].chunk_by(|a, b| a.material == b.material)
+        {
+            let (material, textures) = self.surface(triangles[0].material);
+            render(
+                color_buffer,
+                depth_buffer,
+                triangles,
+                settings,
+                lighting,
+                material,
+                textures,
+            );
+        }
+
+        triangles_to_render.drain(..opaque_triangles);
+    }
+
+    /// Draws transparent triangles of the object in the order they're given, which should be
+    /// from back to front
+    pub fn render_transparent(
+        &self,
+        color_buffer: &mut ColorBuffer,
+        depth_buffer: &mut DepthBuffer,
+        triangles: &[Triangle],
+        settings: &RenderSettings,
+        lighting: &Lighting<'_>,
+    ) {
+        for triangles in triangles.chunk_by(|a, b| a.material == b.material) {
+            let (material, textures) = self.surface(triangles[0].material);
+            render(
+                color_buffer,
+                depth_buffer,
+                triangles,
+                settings,
+                lighting,
+                material,
+                textures,
+            );
+        }
+    }
 }
 
 pub struct Scene {
     objects: Vec<Object>,
     pub camera: Camera,
+    pub lights: Vec<Light>,
+    pub ambient: FloatColor,
+    /// Full screen effects run over each finished frame, in order
+    pub post_effects: Vec<Box<dyn PostEffect>>,
 }
 
 impl Scene {
+    /// Creates an empty scene lit by a white light shining away from the default camera position.
+    /// The light doesn't cast shadows, so scenes only pay for shadow maps when they ask for them.
     pub fn new(camera: Camera) -> Self {
         Self {
             objects: Vec::new(),
             camera,
+            lights: vec![Light {
+                casts_shadows: false,
+                ..Light::directional(Vec3::new(0.0, 0.0, 1.0), FloatColor::WHITE, 0.5)
+            }],
+            ambient: FloatColor::splat(0.5),
+            post_effects: Vec::new(),
         }
     }
 
@@ -33,19 +228,74 @@ impl Scene {
         &self.objects
     }
 
-    pub fn update(
-        &mut self,
+    /// Draws every object in the scene. Each object's triangles are prepared once and its
+    /// opaque surfaces drawn straight away, while the transparent triangles of every object
+    /// are drawn afterwards, together from back to front so they show through each other.
+    pub fn render(
+        &self,
+        color_buffer: &mut ColorBuffer,
+        depth_buffer: &mut DepthBuffer,
+        triangles_to_render: &mut Vec<Triangle>,
+        projection_matrix: Mat4,
         settings: &RenderSettings,
-        window: &mut Window,
-        elapsed_time: f32,
-        delta_time: f32,
+        lighting: &Lighting<'_>,
     ) {
-        let reset_orientation = window.is_key_pressed(Key::P, KeyRepeat::No);
-        if reset_orientation {
-            println!("Mesh orientation reset");
+        // Transparent triangles along with the index of their object
+        let mut transparent_triangles = Vec::new();
+
+        for (index, object) in self.objects.iter().enumerate() {
+            prepare_triangles(
+                triangles_to_render,
+                projection_matrix,
+                &object.mesh,
+                &self.camera,
+                settings,
+                lighting,
+            );
+
+            object.render_opaque(
+                color_buffer,
+                depth_buffer,
+                triangles_to_render,
+                settings,
+                lighting,
+            );
+            transparent_triangles.extend(
+                triangles_to_render
+                    .drain(..)
+                    .map(|triangle| (index, triangle)),
+            );
         }
 
-        // Animate objects
+        transparent_triangles.sort_by(|(_, a), (_, b)| view_depth(b).total_cmp(&view_depth(a)));
+
+        for run in transparent_triangles.chunk_by(|(a, _), (b, _)| a == b) {
+            triangles_to_render.clear();
+            triangles_to_render.extend(run.iter().map(|(_, triangle)| triangle.clone()));
+
+            self.objects[run[0].0].render_transparent(
+                color_buffer,
+                depth_buffer,
+                triangles_to_render,
+                settings,
+                lighting,
+            );
+        }
+    }
+
+    /// Runs the scene's post effects over a finished frame
+    pub fn post_process(&self, color_buffer: &mut ColorBuffer, depth_buffer: &DepthBuffer) {
+        for effect in self.post_effects.iter() {
+            effect.apply(color_buffer, depth_buffer);
+        }
+    }
+
+    pub fn lighting(&self) -> Lighting<'_> {
+        Lighting::new(&self.lights, self.ambient, self.camera.translation)
+    }
+
+    /// Advance the object animations enabled in the render settings
+    pub fn animate(&mut self, settings: &RenderSettings, elapsed_time: f32) {
         for object in self.objects.iter_mut() {
             object.mesh.scale = if settings.scale {
                 Vec3::splat(1.0) * (2.0 * elapsed_time.sin().abs() + 0.05)
@@ -76,10 +326,29 @@ impl Scene {
             } else {
                 object.mesh.initial_translation().z
             };
+        }
+    }
 
-            if reset_orientation {
-                object.mesh.rotation = Vec3::default();
-            }
+    pub fn reset_orientation(&mut self) {
+        for object in self.objects.iter_mut() {
+            object.mesh.rotation = Vec3::default();
+        }
+    }
+
+    /// Animate the scene and move the camera based on keyboard input from the window
+    #[cfg(feature = "window")]
+    pub fn update(
+        &mut self,
+        settings: &RenderSettings,
+        window: &mut Window,
+        elapsed_time: f32,
+        delta_time: f32,
+    ) {
+        self.animate(settings, elapsed_time);
+
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            println!("Mesh orientation reset");
+            self.reset_orientation();
         }
 
         // Update camera direction based on input
@@ -135,3 +404,90 @@ impl Scene {
             * delta_time;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtl::MtlMaterial;
+    use crate::render::RenderMode;
+    use crate::triangle::Face;
+
+    /// A square facing the camera at a depth, drawn with a single MTL material
+    fn square(z: f32, mtl_material: MtlMaterial) -> Object {
+        let vertices = vec![
+            Vec3::new(-1.0, -1.0, z),
+            Vec3::new(1.0, -1.0, z),
+            Vec3::new(1.0, 1.0, z),
+            Vec3::new(-1.0, 1.0, z),
+        ];
+        let color = Color::from(mtl_material.diffuse);
+        let faces = vec![
+            Face::new(0, 2, 1, 0, 0, 0, color).with_material(0),
+            Face::new(0, 3, 2, 0, 0, 0, color).with_material(0),
+        ];
+        let mesh = Mesh::new(
+            vertices,
+            vec![Default::default()],
+            Vec::new(),
+            faces,
+            Vec3::default(),
+            Vec3::splat(1.0),
+            Vec3::default(),
+        )
+        .with_materials(vec![mtl_material]);
+
+        Object::new(
+            mesh,
+            Texture::from_color(1, 1, Color::new(255, 255, 255)),
+            Material::default(),
+        )
+    }
+
+    #[test]
+    fn transparent_surfaces_are_drawn_after_every_opaque_surface() {
+        let mut scene = Scene::new(Camera::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+        ));
+
+        // A dissolved red square in front of an opaque blue one, added first so it would be
+        // hidden if it was drawn before the blue square
+        scene.add_object(square(
+            0.0,
+            MtlMaterial {
+                diffuse: FloatColor::new(1.0, 0.0, 0.0),
+                opacity: 0.5,
+                ..MtlMaterial::new("glass".to_string())
+            },
+        ));
+        scene.add_object(square(
+            1.0,
+            MtlMaterial {
+                diffuse: FloatColor::new(0.0, 0.0, 1.0),
+                ..MtlMaterial::new("paint".to_string())
+            },
+        ));
+
+        let mut settings = RenderSettings::new(32, 32);
+        settings.render_mode = RenderMode::Filled;
+        settings.shaded = false;
+        settings.threads = 1;
+
+        let mut color_buffer = ColorBuffer::new(32, 32);
+        let mut depth_buffer = DepthBuffer::new(32, 32);
+        depth_buffer.clear(1.0);
+        scene.render(
+            &mut color_buffer,
+            &mut depth_buffer,
+            &mut Vec::new(),
+            Mat4::projection(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0),
+            &settings,
+            &scene.lighting(),
+        );
+
+        let center = Color::from(color_buffer.buffer()[16 * 32 + 16]);
+        assert!(center.r > 0 && center.b > 0, "{center:?}");
+    }
+}
@@ -6,22 +6,207 @@ use std::{
 
 use serde::Deserialize;
 
-use crate::{color::Color, mesh::Mesh, texture::Texture, vector::Vec3};
+use crate::{
+    camera::Camera,
+    color::{Color, FloatColor},
+    lighting::{Attenuation, Light},
+    material::Material,
+    mesh::{Mesh, MeshError},
+    post::{ColorGrading, Grayscale, Lut3d, PostEffect, Sharpen, Vignette},
+    shadow::MAX_SPOT_SHADOW_FOV,
+    texture::{Sampler, Texture},
+    vector::Vec3,
+};
 
-use super::Object;
+use super::{Object, Scene};
 
 #[derive(Deserialize)]
 struct SceneObject {
     mesh_path: String,
-    texture_path: String,
+    /// Texture for faces without a material. Objects without one are white.
+    #[serde(default)]
+    texture_path: Option<String>,
+    /// Tangent space normal map for faces without a material
+    #[serde(default)]
+    normal_map_path: Option<String>,
     rotation: Vec3,
     scale: Vec3,
     translation: Vec3,
+    #[serde(default)]
+    material: Material,
+    #[serde(default)]
+    sampler: Sampler,
+}
+
+/// Light types as written in a scene file, with cone angles in degrees
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SceneLightKind {
+    Directional {
+        direction: Vec3,
+    },
+    Point {
+        position: Vec3,
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+}
+
+/// Widest outer angle of a spot light in degrees. A cone of 90° or more would light
+/// everything in front of the light, and then some behind it.
+const MAX_SPOT_OUTER_ANGLE: f32 = 89.0;
+
+fn default_light_color() -> FloatColor {
+    FloatColor::WHITE
+}
+
+fn default_light_intensity() -> f32 {
+    1.0
+}
+
+fn default_cast_shadows() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct SceneLight {
+    #[serde(flatten)]
+    kind: SceneLightKind,
+    #[serde(default = "default_light_color")]
+    color: FloatColor,
+    #[serde(default = "default_light_intensity")]
+    intensity: f32,
+    #[serde(default = "default_cast_shadows")]
+    cast_shadows: bool,
+}
+
+impl From<&SceneLight> for Light {
+    fn from(light: &SceneLight) -> Self {
+        let converted = match light.kind {
+            SceneLightKind::Directional { direction } => {
+                Light::directional(direction, light.color, light.intensity)
+            }
+            SceneLightKind::Point {
+                position,
+                attenuation,
+            } => Light::point(position, attenuation, light.color, light.intensity),
+            SceneLightKind::Spot {
+                position,
+                direction,
+                inner_angle,
+                outer_angle,
+                attenuation,
+            } => {
+                if outer_angle > MAX_SPOT_OUTER_ANGLE {
+                    eprintln!(
+                        "Warning: spot light outer angle of {outer_angle}° clamped to \
+                         {MAX_SPOT_OUTER_ANGLE}°"
+                    );
+                }
+                let outer_angle = outer_angle.min(MAX_SPOT_OUTER_ANGLE);
+                let inner_angle = inner_angle.min(outer_angle);
+
+                if light.cast_shadows && outer_angle * 2.0 > MAX_SPOT_SHADOW_FOV {
+                    eprintln!(
+                        "Warning: spot light cones wider than {MAX_SPOT_SHADOW_FOV}° don't cast \
+                         shadows"
+                    );
+                }
+
+                Light::spot(
+                    position,
+                    direction,
+                    inner_angle.to_radians(),
+                    outer_angle.to_radians(),
+                    attenuation,
+                    light.color,
+                    light.intensity,
+                )
+            }
+        };
+
+        Light {
+            // Point lights can't cast shadows, whatever the scene file says
+            casts_shadows: light.cast_shadows && converted.casts_shadows,
+            ..converted
+        }
+    }
+}
+
+fn default_vignette_radius() -> f32 {
+    0.5
+}
+
+fn default_grading_intensity() -> f32 {
+    1.0
+}
+
+/// Post effects as written in a scene file, with LUT paths relative to the assets directory
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ScenePostEffect {
+    Vignette {
+        strength: f32,
+        #[serde(default = "default_vignette_radius")]
+        radius: f32,
+    },
+    ColorGrading {
+        lut_path: String,
+        #[serde(default = "default_grading_intensity")]
+        intensity: f32,
+    },
+    Sharpen {
+        amount: f32,
+    },
+    Grayscale,
+}
+
+impl ScenePostEffect {
+    /// Creates the effect, or returns `None` if its LUT can't be read
+    fn to_post_effect(&self) -> Option<Box<dyn PostEffect>> {
+        let effect: Box<dyn PostEffect> = match *self {
+            Self::Vignette { strength, radius } => Box::new(Vignette { strength, radius }),
+            Self::ColorGrading {
+                ref lut_path,
+                intensity,
+            } => {
+                let lut = Lut3d::from_cube(&Path::new("assets/").join(lut_path))
+                    .map_err(|err| eprintln!("Error reading LUT: {err}"))
+                    .ok()?;
+                Box::new(ColorGrading { lut, intensity })
+            }
+            Self::Sharpen { amount } => Box::new(Sharpen { amount }),
+            Self::Grayscale => Box::new(Grayscale),
+        };
+
+        Some(effect)
+    }
+}
+
+/// A scene file is either a list of objects, or an object with the list of objects alongside
+/// the scene's lights. Lights that aren't specified fall back to the scene defaults.
+#[derive(Deserialize)]
+struct SceneDescription {
+    objects: Vec<SceneObject>,
+    lights: Option<Vec<SceneLight>>,
+    ambient: Option<FloatColor>,
+    /// Effects run over each finished frame, in order
+    #[serde(default)]
+    post_process: Vec<ScenePostEffect>,
 }
 
 pub enum SceneDeserializeError<'a> {
     ReadError(Cow<'a, str>),
     JsonError(serde_json::Error),
+    MeshError(MeshError<'static>),
 }
 
 impl std::fmt::Display for SceneDeserializeError<'_> {
@@ -33,36 +218,94 @@ impl std::fmt::Display for SceneDeserializeError<'_> {
             Self::JsonError(err) => {
                 write!(f, "{err}")
             }
+            Self::MeshError(err) => {
+                write!(f, "could not read mesh {err}")
+            }
         }
     }
 }
 
-pub fn read_objects_from_scene(path: &Path) -> Result<Vec<Object>, SceneDeserializeError> {
+pub fn read_scene(path: &Path, camera: Camera) -> Result<Scene, SceneDeserializeError<'_>> {
     let json = fs::read_to_string(path)
-        .or_else(|_| Err(SceneDeserializeError::ReadError(path.to_string_lossy())))?;
+        .map_err(|_| SceneDeserializeError::ReadError(path.to_string_lossy()))?;
+
+    let value: serde_json::Value =
+        serde_json::from_str(&json).map_err(SceneDeserializeError::JsonError)?;
+
+    let serialized_scene = if value.is_array() {
+        SceneDescription {
+            objects: serde_json::from_value(value).map_err(SceneDeserializeError::JsonError)?,
+            lights: None,
+            ambient: None,
+            post_process: Vec::new(),
+        }
+    } else {
+        serde_json::from_value(value).map_err(SceneDeserializeError::JsonError)?
+    };
 
-    let serialized_scene: Vec<SceneObject> =
-        serde_json::from_str(&json).map_err(|e| SceneDeserializeError::JsonError(e))?;
+    let mut scene = Scene::new(camera);
 
-    let mut objects = Vec::new();
+    if let Some(lights) = &serialized_scene.lights {
+        scene.lights = lights.iter().map(Light::from).collect();
+    }
+
+    if let Some(ambient) = serialized_scene.ambient {
+        scene.ambient = ambient;
+    }
+
+    // Scenes are still drawn if a LUT can't be read, just without its grading
+    scene.post_effects = serialized_scene
+        .post_process
+        .iter()
+        .filter_map(ScenePostEffect::to_post_effect)
+        .collect();
 
-    for scene_object in serialized_scene.iter() {
+    for scene_object in serialized_scene.objects.iter() {
         let mesh_path = Path::new("assets/").join(&scene_object.mesh_path);
-        let mesh = Mesh::from_obj(
+        let mesh = Mesh::from_file(
             &mesh_path,
             scene_object.rotation,
             scene_object.scale,
             scene_object.translation,
-        );
+        )
+        .map_err(|err| SceneDeserializeError::MeshError(err.into_owned()))?;
+
+        for warning in mesh.warnings.iter() {
+            eprintln!("Warning: {warning}");
+        }
+
+        let mut texture = match &scene_object.texture_path {
+            Some(texture_path) => Texture::from_png(&Path::new("assets/").join(texture_path))
+                .unwrap_or_else(|err| {
+                    eprintln!("Error reading texture: {err}");
+                    Texture::from_color(1, 1, Color::new(0xFF, 0x00, 0xFF))
+                }),
+            None => Texture::from_color(1, 1, Color::new(0xFF, 0xFF, 0xFF)),
+        };
+        texture.sampler = scene_object.sampler;
 
-        let texture_path = Path::new("assets/").join(&scene_object.texture_path);
-        let texture = Texture::from_png(&texture_path).unwrap_or_else(|err| {
-            eprintln!("Error reading texture: {err}");
-            Texture::from_color(1, 1, Color::new(0xFF, 0x00, 0xFF))
-        });
+        let mut object = Object::new(mesh, texture, scene_object.material);
+
+        // Objects are still drawn if their normal map can't be read, just without it
+        if let Some(normal_map_path) = &scene_object.normal_map_path {
+            match Texture::from_png(&Path::new("assets/").join(normal_map_path)) {
+                Ok(normal_map) => object = object.with_normal_map(normal_map),
+                Err(err) => eprintln!("Error reading normal map: {err}"),
+            }
+        }
+
+        if let Some(normal_map) = &mut object.normal_map {
+            normal_map.sampler = scene_object.sampler;
+        }
+        for surface in object.surfaces.iter_mut() {
+            surface.texture.sampler = scene_object.sampler;
+            if let Some(normal_map) = &mut surface.normal_map {
+                normal_map.sampler = scene_object.sampler;
+            }
+        }
 
-        objects.push(Object { mesh, texture });
+        scene.add_object(object);
     }
 
-    Ok(objects)
+    Ok(scene)
 }
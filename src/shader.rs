@@ -0,0 +1,385 @@
+use crate::{
+    color::{Color, FloatColor},
+    color_buffer::ColorBuffer,
+    depth_buffer::DepthBuffer,
+    drawing::PixelState,
+    material::Material,
+    mesh::Mesh,
+    polygon::{ClipVertex, Polygon},
+    render::{clip_and_project, RenderSettings},
+    texture::Tex2,
+    tile::{draw_tiled, ScreenTriangle},
+    vector::{Vec2, Vec3, Vec4},
+};
+
+/// Values output by a vertex shader and interpolated across each triangle for the fragment
+/// shader. Implemented for the vector and color types, and for tuples of them, so most
+/// shaders can use those instead of implementing it themselves.
+pub trait Varyings: Copy + Default {
+    /// Sum of several sets of values, each multiplied by its weight
+    fn weighted_sum<const N: usize>(values: [Self; N], weights: [f32; N]) -> Self;
+
+    /// Values a fraction `t` of the way from `self` to `other`
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Self::weighted_sum([self, other], [1.0 - t, t])
+    }
+}
+
+impl Varyings for () {
+    fn weighted_sum<const N: usize>(_values: [Self; N], _weights: [f32; N]) -> Self {}
+}
+
+macro_rules! impl_varyings_for_vector {
+    ($($ty:ty),*) => {
+        $(
+            impl Varyings for $ty {
+                fn weighted_sum<const N: usize>(values: [Self; N], weights: [f32; N]) -> Self {
+                    values
+                        .into_iter()
+                        .zip(weights)
+                        .fold(Self::default(), |sum, (value, weight)| sum + value * weight)
+                }
+            }
+        )*
+    };
+}
+
+impl_varyings_for_vector!(f32, Vec2, Vec3, Vec4, Tex2, FloatColor);
+
+macro_rules! impl_varyings_for_tuple {
+    ($($name:ident: $index:tt),*) => {
+        impl<$($name: Varyings),*> Varyings for ($($name,)*) {
+            fn weighted_sum<const N: usize>(values: [Self; N], weights: [f32; N]) -> Self {
+                ($($name::weighted_sum(values.map(|value| value.$index), weights),)*)
+            }
+        }
+    };
+}
+
+impl_varyings_for_tuple!(A: 0, B: 1);
+impl_varyings_for_tuple!(A: 0, B: 1, C: 2);
+impl_varyings_for_tuple!(A: 0, B: 1, C: 2, D: 3);
+
+/// A vertex of a mesh face, as read by a vertex shader
+#[derive(Clone, Copy, Debug)]
+pub struct VertexInput {
+    /// Position in model space
+    pub position: Vec3,
+    /// Model space vertex normal, or the face normal if the face has no vertex normals
+    pub normal: Vec3,
+    pub uv: Tex2,
+    /// Color of the face the vertex belongs to
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VertexOutput<V> {
+    /// Position in clip space, usually the vertex transformed by the world, view and projection
+    /// matrices
+    pub position: Vec4,
+    pub varyings: V,
+}
+
+/// Transforms each vertex of a mesh into clip space. Anything else the shader needs, such as
+/// its matrices, is held by the type implementing it.
+pub trait VertexShader {
+    type Varyings: Varyings;
+
+    fn shade_vertex(&self, vertex: &VertexInput) -> VertexOutput<Self::Varyings>;
+}
+
+/// A pixel covered by a triangle, with the varyings interpolated perspective-correctly
+#[derive(Clone, Copy, Debug)]
+pub struct Fragment<V> {
+    pub x: u16,
+    pub y: u16,
+    /// Depth the pixel will write to the depth buffer, from 0 at the camera to 1 far away
+    pub depth: f32,
+    pub varyings: V,
+}
+
+/// Colors each pixel of a triangle from its interpolated varyings
+pub trait FragmentShader<V> {
+    /// Returns the pixel's color, or None to discard it
+    fn shade_fragment(&self, fragment: &Fragment<V>) -> Option<Color>;
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct ShadedVertex<V> {
+    pos: Vec4,
+    varyings: V,
+}
+
+impl<V: Varyings> ClipVertex for ShadedVertex<V> {
+    fn position(&self) -> Vec4 {
+        self.pos
+    }
+
+    fn set_position(&mut self, position: Vec4) {
+        self.pos = position;
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            pos: self.pos + t * (other.pos - self.pos),
+            varyings: self.varyings.lerp(other.varyings, t),
+        }
+    }
+}
+
+/// A triangle output by a vertex shader, clipped and projected into screen space
+#[derive(Clone, Debug)]
+pub struct ShadedTriangle<V> {
+    /// Screen x and y, normalized depth in z and the original w
+    pub points: [Vec4; 3],
+    pub varyings: [V; 3],
+}
+
+impl<V: Varyings> ShadedTriangle<V> {
+    /// Interpolated 1/w at a point, given its barycentric coordinates
+    pub fn interpolate_reciprocal_w(&self, alpha: f32, beta: f32, gamma: f32) -> f32 {
+        1.0 / self.points[0].w * alpha
+            + 1.0 / self.points[1].w * beta
+            + 1.0 / self.points[2].w * gamma
+    }
+
+    /// Perspective correct interpolation of the varyings at a point
+    pub fn interpolate_varyings(&self, alpha: f32, beta: f32, gamma: f32, reciprocal_w: f32) -> V {
+        V::weighted_sum(
+            self.varyings,
+            [
+                alpha / self.points[0].w / reciprocal_w,
+                beta / self.points[1].w / reciprocal_w,
+                gamma / self.points[2].w / reciprocal_w,
+            ],
+        )
+    }
+}
+
+impl<V> ScreenTriangle for ShadedTriangle<V> {
+    fn screen_points(&self) -> &[Vec4; 3] {
+        &self.points
+    }
+}
+
+/// Runs a vertex shader over every face of a mesh, then clips and projects the results into
+/// triangles ready to be drawn with [`render_shaded`]
+pub fn prepare_shaded_triangles<S: VertexShader>(
+    triangles_to_render: &mut Vec<ShadedTriangle<S::Varyings>>,
+    mesh: &Mesh,
+    shader: &S,
+    settings: &RenderSettings,
+) {
+    triangles_to_render.clear();
+
+    for face in mesh.faces.iter() {
+        let positions = [face.a, face.b, face.c].map(|i| mesh.vertices[i as usize]);
+        let uvs = [face.a_uv, face.b_uv, face.c_uv].map(|i| mesh.vertex_uvs[i as usize]);
+
+        let normals = match face.normals {
+            Some(normal_indices) => normal_indices.map(|i| mesh.vertex_normals[i as usize]),
+            None => {
+                let ab = positions[1] - positions[0];
+                let ac = positions[2] - positions[0];
+
+                [ab.cross(ac).normalized(); 3]
+            }
+        };
+
+        let vertices = [0, 1, 2].map(|i| {
+            let output = shader.shade_vertex(&VertexInput {
+                position: positions[i],
+                normal: normals[i],
+                uv: uvs[i],
+                color: face.color,
+            });
+
+            ShadedVertex {
+                pos: output.position,
+                varyings: output.varyings,
+            }
+        });
+
+        let polygon = Polygon::new(vertices.into_iter().collect());
+
+        for triangle in clip_and_project(&polygon, settings.render_width, settings.render_height) {
+            let points = triangle.map(|vertex| vertex.pos);
+
+            // Triangles wound the other way face away from the camera
+            if settings.backface_cull {
+                let [a, b, c] = points;
+
+                if (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x) < 0.0 {
+                    continue;
+                }
+            }
+
+            triangles_to_render.push(ShadedTriangle {
+                points,
+                varyings: triangle.map(|vertex| vertex.varyings),
+            });
+        }
+    }
+}
+
+/// Draws triangles prepared by [`prepare_shaded_triangles`], coloring their pixels with a
+/// fragment shader. The material's blend mode, opacity and alpha cutoff are applied to the
+/// colors the shader returns, which are blended in linear space when linear lighting is on.
+pub fn render_shaded<V, F>(
+    color_buffer: &mut ColorBuffer,
+    depth_buffer: &mut DepthBuffer,
+    triangles_to_render: &[ShadedTriangle<V>],
+    shader: &F,
+    settings: &RenderSettings,
+    material: &Material,
+) where
+    V: Varyings + Sync,
+    F: FragmentShader<V> + Sync,
+{
+    let pixel_state = PixelState {
+        pixel_lighting: None,
+        blend_mode: material.blend_mode,
+        opacity: material.opacity,
+        alpha_cutoff: material.alpha_cutoff,
+        simd: settings.simd_rasterization,
+        linear: settings.linear_lighting,
+    };
+
+    let mut triangles: Vec<&ShadedTriangle<V>> = triangles_to_render.iter().collect();
+
+    // Blended triangles are drawn back to front, so the ones behind show through
+    if material.is_transparent() {
+        let view_depth = |triangle: &ShadedTriangle<V>| {
+            triangle.points.iter().map(|point| point.w).sum::<f32>() / 3.0
+        };
+
+        triangles.sort_by(|a, b| view_depth(b).total_cmp(&view_depth(a)));
+    }
+
+    let draw_triangle = |color_buffer: &mut ColorBuffer,
+                         depth_buffer: &mut DepthBuffer,
+                         triangle: &ShadedTriangle<V>| {
+        color_buffer.draw_shaded_triangle(triangle, shader, depth_buffer, pixel_state);
+    };
+
+    if settings.threads > 1 {
+        draw_tiled(
+            color_buffer,
+            depth_buffer,
+            &triangles,
+            settings.threads,
+            draw_triangle,
+        );
+    } else {
+        for triangle in triangles {
+            draw_triangle(color_buffer, depth_buffer, triangle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{camera::Camera, matrix::Mat4, plane::Plane, triangle::Face};
+
+    struct ColorShader {
+        view_projection: Mat4,
+    }
+
+    impl VertexShader for ColorShader {
+        type Varyings = (FloatColor, f32);
+
+        fn shade_vertex(&self, vertex: &VertexInput) -> VertexOutput<Self::Varyings> {
+            VertexOutput {
+                position: self.view_projection * Vec4::from(vertex.position),
+                varyings: (FloatColor::new(vertex.uv.u, vertex.uv.v, 0.0), 1.0),
+            }
+        }
+    }
+
+    impl FragmentShader<(FloatColor, f32)> for ColorShader {
+        fn shade_fragment(&self, fragment: &Fragment<(FloatColor, f32)>) -> Option<Color> {
+            let (color, brightness) = fragment.varyings;
+
+            Some(Color::from(color * brightness))
+        }
+    }
+
+    #[test]
+    fn clipping_interpolates_varyings() {
+        // The varying holds each vertex's x, so clipped vertices should keep it in step
+        let vertex = |x: f32, y: f32| ShadedVertex {
+            pos: Vec4::new(x, y, 0.5, 1.0),
+            varyings: x,
+        };
+        let polygon = Polygon::new(
+            [vertex(-0.5, 0.0), vertex(3.0, 0.5), vertex(3.0, -0.5)]
+                .into_iter()
+                .collect(),
+        );
+
+        let clipped = Plane::Right.clip_polygon(&polygon);
+
+        assert_eq!(clipped.vertices().len(), 3);
+        for vertex in clipped.vertices() {
+            assert!(vertex.pos.x <= 1.0);
+            assert!((vertex.varyings - vertex.pos.x).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn fragment_shader_receives_interpolated_varyings() {
+        let mesh = Mesh::new(
+            vec![
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+            ],
+            vec![
+                Tex2::new(1.0, 0.0),
+                Tex2::new(0.0, 1.0),
+                Tex2::new(0.0, 0.0),
+            ],
+            Vec::new(),
+            vec![Face::new(0, 1, 2, 0, 1, 2, Color::new(255, 255, 255))],
+            Vec3::default(),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::default(),
+        );
+
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+        );
+        let shader = ColorShader {
+            view_projection: Mat4::projection(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 10.0)
+                * camera.view_matrix(),
+        };
+
+        let mut settings = RenderSettings::new(64, 64);
+        settings.threads = 1;
+
+        let mut triangles = Vec::new();
+        prepare_shaded_triangles(&mut triangles, &mesh, &shader, &settings);
+        assert_eq!(triangles.len(), 1);
+
+        let mut color_buffer = ColorBuffer::new(64, 64);
+        let mut depth_buffer = DepthBuffer::new(64, 64);
+        render_shaded(
+            &mut color_buffer,
+            &mut depth_buffer,
+            &triangles,
+            &shader,
+            &settings,
+            &Material::default(),
+        );
+
+        // Near the vertex with uv (1, 0) the pixel is red, near the one with uv (0, 1) green
+        let red = Color::from(color_buffer.buffer()[41 * 64 + 22]);
+        let green = Color::from(color_buffer.buffer()[23 * 64 + 22]);
+        assert!(red.r > 200 && red.g < 50, "{red:?}");
+        assert!(green.g > 200 && green.r < 50, "{green:?}");
+    }
+}
@@ -0,0 +1,391 @@
+use tinyvec::ArrayVec;
+
+use crate::depth_buffer::DepthBuffer;
+use crate::lighting::{Light, LightKind};
+use crate::matrix::Mat4;
+use crate::mesh::Mesh;
+use crate::polygon::{Polygon, PolygonVertex};
+use crate::render::clip_and_project;
+use crate::scene::Scene;
+use crate::vector::{Vec3, Vec4};
+
+/// Widest cone in degrees a spot light's shadow map can cover. Perspective projections
+/// collapse as their field of view approaches 180°, so spot lights with wider cones don't
+/// cast shadows.
+pub const MAX_SPOT_SHADOW_FOV: f32 = 170.0;
+
+/// Settings for rendering and sampling shadow maps
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Width and height of each shadow map in pixels
+    pub map_size: u16,
+    /// Offset subtracted from a surface's depth before comparing it against the shadow map,
+    /// which stops surfaces from shadowing themselves. Depths range from 0 to 1.
+    pub bias: f32,
+    /// Radius in texels of the percentage-closer filter used to soften shadow edges.
+    /// A radius of 0 gives hard shadows.
+    pub pcf_radius: u8,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            map_size: 1024,
+            bias: 0.005,
+            pcf_radius: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ShadowProjection {
+    Orthographic,
+    Perspective { znear: f32, zfar: f32 },
+}
+
+impl ShadowProjection {
+    /// Depth between the near and far planes in [0, 1], linear in view space distance, from a
+    /// projected point holding its normalized depth in z and clip space w
+    fn linear_depth(&self, projected: Vec4) -> f32 {
+        match self {
+            // Orthographic depth is already linear
+            Self::Orthographic => projected.z,
+            Self::Perspective { znear, zfar } => (projected.w - znear) / (zfar - znear),
+        }
+    }
+}
+
+/// Depth of the scene rendered from a light's point of view
+#[derive(Debug)]
+pub struct ShadowMap {
+    depth_buffer: DepthBuffer,
+    view_projection: Mat4,
+    projection: ShadowProjection,
+}
+
+impl ShadowMap {
+    /// Renders a shadow map for a light. Directional lights use an orthographic projection
+    /// fitted around the scene, and spot lights use a perspective projection covering their
+    /// cone. Point lights and spot lights wider than `MAX_SPOT_SHADOW_FOV` don't cast shadows,
+    /// so no map is rendered for them.
+    pub fn render(light: &Light, scene: &Scene, settings: &ShadowSettings) -> Option<Self> {
+        if !light.casts_shadows {
+            return None;
+        }
+
+        let (center, radius) = scene_bounds(scene)?;
+
+        let (view_projection, projection) = match light.kind {
+            LightKind::Directional { direction } => {
+                let eye = center - direction * (radius * 2.0);
+                let view = Mat4::look_to(eye, direction, up_vector(direction));
+                let orthographic =
+                    Mat4::orthographic(-radius, radius, -radius, radius, radius, radius * 3.0);
+
+                (orthographic * view, ShadowProjection::Orthographic)
+            }
+            LightKind::Spot {
+                position,
+                direction,
+                outer_cos,
+                ..
+            } => {
+                let fov = outer_cos.acos() * 2.0;
+                if fov > MAX_SPOT_SHADOW_FOV.to_radians() {
+                    return None;
+                }
+
+                let distance = (center - position).magnitude();
+                let znear = (distance - radius).max(0.1);
+                let zfar = (distance + radius).max(znear + 0.1);
+
+                let view = Mat4::look_to(position, direction, up_vector(direction));
+                let perspective = Mat4::projection(fov, 1.0, znear, zfar);
+
+                (
+                    perspective * view,
+                    ShadowProjection::Perspective { znear, zfar },
+                )
+            }
+            LightKind::Point { .. } => return None,
+        };
+
+        let mut shadow_map = Self {
+            depth_buffer: DepthBuffer::new(settings.map_size, settings.map_size),
+            view_projection,
+            projection,
+        };
+
+        for object in scene.objects() {
+            shadow_map.draw_mesh(&object.mesh.world_matrix(), &object.mesh);
+        }
+
+        Some(shadow_map)
+    }
+
+    fn draw_mesh(&mut self, world_matrix: &Mat4, mesh: &Mesh) {
+        let transform = self.view_projection * *world_matrix;
+        let size = self.depth_buffer.width();
+
+        // Both sides of every face are drawn, so meshes that aren't closed still cast shadows
+        for face in mesh.faces.iter() {
+            let mut polygon_verts = ArrayVec::new();
+
+            // Only depth is drawn, so the vertices carry no attributes
+            for vertex_index in [face.a, face.b, face.c] {
+                polygon_verts.push(PolygonVertex::<0> {
+                    pos: transform * Vec4::from(mesh.vertices[vertex_index as usize]),
+                    ..Default::default()
+                });
+            }
+
+            for triangle in clip_and_project(&Polygon::new(polygon_verts), size, size) {
+                let points = triangle.map(|vertex| {
+                    let mut point = vertex.pos;
+                    point.z = self.projection.linear_depth(point);
+                    point
+                });
+
+                self.depth_buffer.draw_depth_triangle(&points);
+            }
+        }
+    }
+
+    /// Returns how much of the light reaches a point in world space, from 0 (fully shadowed)
+    /// to 1 (fully lit)
+    pub fn visibility(&self, position: Vec3, settings: &ShadowSettings) -> f32 {
+        let clip = self.view_projection * Vec4::from(position);
+
+        if clip.w <= 0.0 {
+            return 1.0;
+        }
+
+        let projected = Vec4::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w, clip.w);
+        let depth = self.projection.linear_depth(projected) - settings.bias;
+
+        if depth > 1.0 {
+            return 1.0;
+        }
+
+        // Same mapping into the map as used when projecting triangles
+        let size = self.depth_buffer.width();
+        let x = ((projected.x + 1.0) * (size as f32 - 1.0) / 2.0).round() as i32;
+        let y = ((projected.y - 1.0) * (size as f32 - 1.0) / -2.0).round() as i32;
+
+        let radius = settings.pcf_radius as i32;
+        let mut samples = 0;
+        let mut lit_samples = 0;
+
+        for sample_y in y - radius..=y + radius {
+            for sample_x in x - radius..=x + radius {
+                samples += 1;
+
+                // Anything outside of the map is outside of the light's view, so it is lit
+                if sample_x < 0
+                    || sample_y < 0
+                    || sample_x >= size as i32
+                    || sample_y >= size as i32
+                    || depth <= self.depth_buffer.get(sample_x as u16, sample_y as u16)
+                {
+                    lit_samples += 1;
+                }
+            }
+        }
+
+        lit_samples as f32 / samples as f32
+    }
+}
+
+/// Renders a shadow map for each of the scene's lights, in the same order as the lights.
+/// Lights that don't cast shadows have no map.
+pub fn render_shadow_maps(scene: &Scene, settings: &ShadowSettings) -> Vec<Option<ShadowMap>> {
+    if !settings.enabled {
+        return Vec::new();
+    }
+
+    scene
+        .lights
+        .iter()
+        .map(|light| ShadowMap::render(light, scene, settings))
+        .collect()
+}
+
+/// Picks an up vector for a light's view that isn't parallel to its direction
+fn up_vector(direction: Vec3) -> Vec3 {
+    if direction.normalized().y.abs() > 0.99 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+/// Returns the center and radius of a sphere enclosing every object in the scene
+fn scene_bounds(scene: &Scene) -> Option<(Vec3, f32)> {
+    let mut min = Vec3::splat(f32::INFINITY);
+    let mut max = Vec3::splat(f32::NEG_INFINITY);
+
+    for object in scene.objects() {
+        let world_matrix = object.mesh.world_matrix();
+
+        for &vertex in object.mesh.vertices.iter() {
+            let world_vertex = Vec3::from(world_matrix * Vec4::from(vertex));
+
+            min = Vec3::new(
+                min.x.min(world_vertex.x),
+                min.y.min(world_vertex.y),
+                min.z.min(world_vertex.z),
+            );
+            max = Vec3::new(
+                max.x.max(world_vertex.x),
+                max.y.max(world_vertex.y),
+                max.z.max(world_vertex.z),
+            );
+        }
+    }
+
+    if min.x > max.x {
+        return None;
+    }
+
+    let center = (min + max) / 2.0;
+    let radius = ((max - min).magnitude() / 2.0).max(0.01);
+
+    Some((center, radius))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::color::{Color, FloatColor};
+    use crate::material::Material;
+    use crate::scene::Object;
+    use crate::texture::Texture;
+    use crate::triangle::Face;
+
+    fn quad(half_size: f32, height: f32) -> Object {
+        let vertices = vec![
+            Vec3::new(-half_size, height, -half_size),
+            Vec3::new(half_size, height, -half_size),
+            Vec3::new(half_size, height, half_size),
+            Vec3::new(-half_size, height, half_size),
+        ];
+        let faces = vec![
+            Face::new(0, 1, 2, 0, 0, 0, Color::new(255, 255, 255)),
+            Face::new(2, 3, 0, 0, 0, 0, Color::new(255, 255, 255)),
+        ];
+
+        Object::new(
+            Mesh::new(
+                vertices,
+                Vec::new(),
+                Vec::new(),
+                faces,
+                Vec3::default(),
+                Vec3::splat(1.0),
+                Vec3::default(),
+            ),
+            Texture::from_color(1, 1, Color::new(255, 255, 255)),
+            Material::default(),
+        )
+    }
+
+    #[test]
+    fn occluder_shadows_points_below_it() {
+        let mut scene = Scene::new(Camera::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+        ));
+        scene.add_object(quad(4.0, 0.0));
+        scene.add_object(quad(1.0, 1.0));
+
+        let light = Light::directional(Vec3::new(0.0, -1.0, 0.0), FloatColor::WHITE, 1.0);
+        let settings = ShadowSettings {
+            pcf_radius: 0,
+            ..Default::default()
+        };
+        let shadow_map = ShadowMap::render(&light, &scene, &settings).unwrap();
+
+        assert_eq!(
+            shadow_map.visibility(Vec3::new(0.0, 0.0, 0.0), &settings),
+            0.0
+        );
+        assert_eq!(
+            shadow_map.visibility(Vec3::new(3.0, 0.0, 3.0), &settings),
+            1.0
+        );
+        assert_eq!(
+            shadow_map.visibility(Vec3::new(0.0, 1.0, 0.0), &settings),
+            1.0
+        );
+    }
+
+    #[test]
+    fn point_lights_have_no_shadow_map() {
+        let mut scene = Scene::new(Camera::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+        ));
+        scene.add_object(quad(1.0, 0.0));
+
+        // Even when asked to cast shadows
+        let light = Light {
+            casts_shadows: true,
+            ..Light::point(
+                Vec3::new(0.0, 2.0, 0.0),
+                Default::default(),
+                FloatColor::WHITE,
+                1.0,
+            )
+        };
+
+        assert!(ShadowMap::render(&light, &scene, &ShadowSettings::default()).is_none());
+    }
+
+    #[test]
+    fn spot_lights_too_wide_for_a_shadow_map_have_none() {
+        let mut scene = Scene::new(Camera::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+        ));
+        scene.add_object(quad(1.0, 0.0));
+
+        let spot = |outer_angle: f32| {
+            Light::spot(
+                Vec3::new(0.0, 2.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                0.0,
+                outer_angle.to_radians(),
+                Default::default(),
+                FloatColor::WHITE,
+                1.0,
+            )
+        };
+
+        let settings = ShadowSettings::default();
+        assert!(ShadowMap::render(&spot(45.0), &scene, &settings).is_some());
+        assert!(ShadowMap::render(&spot(100.0), &scene, &settings).is_none());
+    }
+
+    #[test]
+    fn default_light_has_no_shadow_map() {
+        let mut scene = Scene::new(Camera::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+        ));
+        scene.add_object(quad(1.0, 0.0));
+
+        let shadow_maps = render_shadow_maps(&scene, &ShadowSettings::default());
+        assert!(shadow_maps.iter().all(Option::is_none));
+    }
+}
@@ -0,0 +1,137 @@
+/// Number of neighbouring pixels in a row whose edge weights are evaluated together
+pub const LANES: usize = 4;
+
+/// Coverage and barycentric coordinates of a span of pixels in a row
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EdgeSpan {
+    /// Bit n is set if the nth pixel of the span is inside the triangle
+    pub coverage: u32,
+    pub alpha: [f32; LANES],
+    pub beta: [f32; LANES],
+    pub gamma: [f32; LANES],
+}
+
+impl EdgeSpan {
+    /// Evaluates the three edge weights of a triangle for a span of pixels, given the raw
+    /// fixed point weights at the first pixel, how much they increase from one pixel to the
+    /// next, and the triangle's area as an f32. Barycentric coordinates are only meaningful
+    /// for covered pixels.
+    pub fn evaluate(weights: [i32; 3], steps: [i32; 3], area: f32) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        {
+            // SAFETY: SSE2 is part of the x86_64 baseline, so it is always available
+            unsafe { evaluate_sse2(weights, steps, area) }
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            evaluate_scalar(weights, steps, area)
+        }
+    }
+}
+
+/// Converts a raw edge weight into the same f32 value as the scalar rasterizer
+fn weight_to_f32(weight: i32) -> f32 {
+    weight as f32 * 256.0
+}
+
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+fn evaluate_scalar(weights: [i32; 3], steps: [i32; 3], area: f32) -> EdgeSpan {
+    let mut span = EdgeSpan::default();
+
+    for lane in 0..LANES {
+        let [w0, w1, w2] = [0, 1, 2].map(|edge| weights[edge] + steps[edge] * lane as i32);
+
+        if w0 >= 0 && w1 >= 0 && w2 >= 0 {
+            span.coverage |= 1 << lane;
+            span.alpha[lane] = weight_to_f32(w1) / area;
+            span.beta[lane] = weight_to_f32(w2) / area;
+            span.gamma[lane] = weight_to_f32(w0) / area;
+        }
+    }
+
+    span
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+fn evaluate_sse2(weights: [i32; 3], steps: [i32; 3], area: f32) -> EdgeSpan {
+    use core::arch::x86_64::*;
+
+    let edge_weights = [0, 1, 2].map(|edge| {
+        let step = steps[edge];
+        _mm_add_epi32(
+            _mm_set1_epi32(weights[edge]),
+            _mm_setr_epi32(0, step, step * 2, step * 3),
+        )
+    });
+
+    // A pixel is covered when none of its weights are negative, so when the sign bit of the
+    // weights ORed together is clear
+    let any_negative = _mm_or_si128(
+        _mm_or_si128(edge_weights[0], edge_weights[1]),
+        edge_weights[2],
+    );
+    let coverage = !_mm_movemask_ps(_mm_castsi128_ps(any_negative)) as u32 & 0b1111;
+
+    let mut span = EdgeSpan {
+        coverage,
+        ..Default::default()
+    };
+
+    if coverage == 0 {
+        return span;
+    }
+
+    // Same operations as weight_to_f32 followed by the division, so results match the
+    // scalar path exactly
+    let scale = _mm_set1_ps(256.0);
+    let area = _mm_set1_ps(area);
+    let barycentric =
+        |weight: __m128i| _mm_div_ps(_mm_mul_ps(_mm_cvtepi32_ps(weight), scale), area);
+
+    // SAFETY: each array holds exactly four f32s, and unaligned stores are allowed
+    unsafe {
+        _mm_storeu_ps(span.alpha.as_mut_ptr(), barycentric(edge_weights[1]));
+        _mm_storeu_ps(span.beta.as_mut_ptr(), barycentric(edge_weights[2]));
+        _mm_storeu_ps(span.gamma.as_mut_ptr(), barycentric(edge_weights[0]));
+    }
+
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simd_span_matches_scalar_span() {
+        let area = weight_to_f32(48_000);
+
+        for (weights, steps) in [
+            ([1_000, 20_000, 3_000], [-400, 250, 150]),
+            ([-600, 30_000, 12_000], [300, -5_000, 4_700]),
+            ([-5, -5, -5], [-1, -1, -1]),
+            ([0, 0, 0], [0, 0, 0]),
+        ] {
+            let span = EdgeSpan::evaluate(weights, steps, area);
+            let scalar_span = evaluate_scalar(weights, steps, area);
+
+            assert_eq!(span.coverage, scalar_span.coverage);
+
+            // Barycentric coordinates are only meaningful for covered pixels
+            for lane in (0..LANES).filter(|lane| span.coverage & (1 << lane) != 0) {
+                assert_eq!(span.alpha[lane], scalar_span.alpha[lane]);
+                assert_eq!(span.beta[lane], scalar_span.beta[lane]);
+                assert_eq!(span.gamma[lane], scalar_span.gamma[lane]);
+            }
+        }
+    }
+
+    #[test]
+    fn coverage_marks_pixels_inside_all_edges() {
+        let span = EdgeSpan::evaluate([-256, 1_000, 1_000], [256, -10, -10], 1.0);
+
+        assert_eq!(span.coverage, 0b1110);
+    }
+}
@@ -0,0 +1,295 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+use crate::color::Color;
+use crate::mesh::Mesh;
+use crate::obj::read_numbers;
+use crate::polygon::triangulate_face;
+use crate::texture::Tex2;
+use crate::triangle::Face;
+use crate::vector::Vec3;
+
+/// Size of a binary STL file's header, followed by its triangle count
+const BINARY_HEADER_SIZE: usize = 84;
+/// Size of each triangle of a binary STL file: a normal, three vertices and an attribute
+/// byte count
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+/// Why an STL file could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StlErrorReason {
+    OpenError,
+    /// The file ends before all of the triangles in its header
+    TruncatedFile,
+    InvalidVertex,
+    /// A facet has fewer than three vertices
+    InvalidFacet,
+}
+
+impl std::fmt::Display for StlErrorReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OpenError => write!(f, "could not open file for reading"),
+            Self::TruncatedFile => write!(f, "file is shorter than its triangle count"),
+            Self::InvalidVertex => write!(f, "invalid vertex position"),
+            Self::InvalidFacet => write!(f, "facet has fewer than three vertices"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StlError<'a> {
+    pub path: Cow<'a, str>,
+    /// Line of an ASCII file the error was found on, starting at 1
+    pub line: Option<usize>,
+    pub reason: StlErrorReason,
+}
+
+impl StlError<'_> {
+    pub fn into_owned(self) -> StlError<'static> {
+        StlError {
+            path: Cow::Owned(self.path.into_owned()),
+            line: self.line,
+            reason: self.reason,
+        }
+    }
+}
+
+impl std::fmt::Display for StlError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {line}): {}", self.path, self.reason),
+            None => write!(f, "{}: {}", self.path, self.reason),
+        }
+    }
+}
+
+impl Mesh {
+    /// Reads an ASCII or binary STL file. STL files only store triangles, so every face gets
+    /// its own vertices and a flat normal generated from its winding.
+    pub fn from_stl(
+        stl_file_path: &Path,
+        rotation: Vec3,
+        scale: Vec3,
+        translation: Vec3,
+    ) -> Result<Self, StlError<'_>> {
+        let error = |line, reason| StlError {
+            path: stl_file_path.to_string_lossy(),
+            line,
+            reason,
+        };
+
+        let bytes = fs::read(stl_file_path).map_err(|_| error(None, StlErrorReason::OpenError))?;
+
+        let triangles = read_stl(&bytes).map_err(|(line, reason)| error(line, reason))?;
+
+        Ok(mesh_from_triangles(triangles, rotation, scale, translation))
+    }
+}
+
+/// Reads the triangles of an ASCII or binary STL file, or returns the line of an ASCII file
+/// an error was found on and why
+fn read_stl(bytes: &[u8]) -> Result<Vec<[Vec3; 3]>, (Option<usize>, StlErrorReason)> {
+    if is_ascii_stl(bytes) {
+        let triangles = read_ascii_stl(&String::from_utf8_lossy(bytes))
+            .map_err(|(line, reason)| (Some(line), reason))?;
+
+        // Truncated binary files whose header starts with "solid" don't have the right size
+        // for a binary file either, but have no facets when read as text
+        if !triangles.is_empty() {
+            return Ok(triangles);
+        }
+    }
+
+    read_binary_stl(bytes).map_err(|reason| (None, reason))
+}
+
+/// ASCII files start with "solid", but so do the headers of some binary files. Binary files
+/// can still be told apart by their size matching their triangle count, unless they're
+/// truncated.
+fn is_ascii_stl(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(b"solid") {
+        return false;
+    }
+
+    match bytes.get(80..BINARY_HEADER_SIZE) {
+        Some(count) => {
+            let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+            bytes.len() != BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE
+        }
+        None => true,
+    }
+}
+
+fn read_binary_stl(bytes: &[u8]) -> Result<Vec<[Vec3; 3]>, StlErrorReason> {
+    let count = bytes
+        .get(80..BINARY_HEADER_SIZE)
+        .ok_or(StlErrorReason::TruncatedFile)?;
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+
+    let data = bytes
+        .get(BINARY_HEADER_SIZE..BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE)
+        .ok_or(StlErrorReason::TruncatedFile)?;
+
+    let read_f32 = |bytes: &[u8], index: usize| {
+        f32::from_le_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap())
+    };
+
+    Ok(data
+        .chunks_exact(BINARY_TRIANGLE_SIZE)
+        .map(|triangle| {
+            // The stored normal is skipped, since many exporters leave it zeroed
+            [1, 2, 3].map(|vertex| {
+                Vec3::new(
+                    read_f32(triangle, vertex * 3),
+                    read_f32(triangle, vertex * 3 + 1),
+                    read_f32(triangle, vertex * 3 + 2),
+                )
+            })
+        })
+        .collect())
+}
+
+/// Reads the triangles of an ASCII STL file, or returns the line number an error was found
+/// on and why
+fn read_ascii_stl(text: &str) -> Result<Vec<[Vec3; 3]>, (usize, StlErrorReason)> {
+    let mut triangles = Vec::new();
+    let mut facet_vertices = Vec::new();
+
+    // A facet should look like this, though some exporters write more than three vertices:
+    // facet normal <nx> <ny> <nz>
+    //   outer loop
+    //     vertex <x> <y> <z>
+    //     ...
+    //   endloop
+    // endfacet
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.trim_start();
+
+        if line.starts_with("vertex") {
+            let position =
+                read_numbers::<3, 3>(line).ok_or((line_number, StlErrorReason::InvalidVertex))?;
+            facet_vertices.push(Vec3::new(position[0], position[1], position[2]));
+        } else if line.starts_with("endloop") {
+            if facet_vertices.len() < 3 {
+                return Err((line_number, StlErrorReason::InvalidFacet));
+            }
+
+            triangles.extend(
+                triangulate_face(&facet_vertices)
+                    .into_iter()
+                    .map(|[a, b, c]| [facet_vertices[a], facet_vertices[b], facet_vertices[c]]),
+            );
+            facet_vertices.clear();
+        }
+    }
+
+    Ok(triangles)
+}
+
+fn mesh_from_triangles(
+    triangles: Vec<[Vec3; 3]>,
+    rotation: Vec3,
+    scale: Vec3,
+    translation: Vec3,
+) -> Mesh {
+    let mut vertices = Vec::with_capacity(triangles.len() * 3);
+    let mut normals = Vec::with_capacity(triangles.len());
+    let mut faces = Vec::with_capacity(triangles.len());
+
+    for [a, b, c] in triangles {
+        let first_vertex = vertices.len() as u32;
+        let normal = normals.len() as u32;
+
+        vertices.extend([a, b, c]);
+        normals.push((b - a).cross(c - a).normalized_or_zero());
+        faces.push(
+            Face::new(
+                first_vertex,
+                first_vertex + 1,
+                first_vertex + 2,
+                0,
+                0,
+                0,
+                Color::new(0, 0xFF, 0xFF),
+            )
+            .with_normals(normal, normal, normal),
+        );
+    }
+
+    // STL has no texture coordinates, so every vertex uses the same one
+    Mesh::new(
+        vertices,
+        vec![Tex2::default()],
+        normals,
+        faces,
+        rotation,
+        scale,
+        translation,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_and_binary_files_give_the_same_triangles() {
+        let ascii = "solid square\n\
+                     facet normal 0 0 0\n\
+                       outer loop\n\
+                         vertex 0 0 0\n\
+                         vertex 1 0 0\n\
+                         vertex 1 1 0\n\
+                         vertex 0 1 0\n\
+                       endloop\n\
+                     endfacet\n\
+                     endsolid square\n";
+
+        // A binary header starting with "solid" shouldn't be mistaken for an ASCII file
+        let mut binary = b"solid but binary".to_vec();
+        binary.resize(80, 0);
+        binary.extend(2u32.to_le_bytes());
+        for triangle in [
+            [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0f32],
+            [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+        ] {
+            binary.extend([0u8; 12]);
+            binary.extend(triangle.iter().flat_map(|value| value.to_le_bytes()));
+            binary.extend([0u8; 2]);
+        }
+
+        assert!(is_ascii_stl(ascii.as_bytes()));
+        assert!(!is_ascii_stl(&binary));
+        assert_eq!(read_ascii_stl(ascii), Ok(read_binary_stl(&binary).unwrap()));
+
+        // Normals are generated from each triangle's winding
+        let mesh = mesh_from_triangles(
+            read_binary_stl(&binary).unwrap(),
+            Vec3::default(),
+            Vec3::splat(1.0),
+            Vec3::default(),
+        );
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.vertex_normals, [Vec3::new(0.0, 0.0, 1.0); 2]);
+    }
+
+    #[test]
+    fn truncated_binary_files_are_rejected() {
+        let mut binary = vec![0u8; 80];
+        binary.extend(3u32.to_le_bytes());
+        binary.extend([0u8; BINARY_TRIANGLE_SIZE]);
+
+        assert_eq!(read_binary_stl(&binary), Err(StlErrorReason::TruncatedFile));
+
+        // Even when the header looks like the start of an ASCII file
+        binary[..5].copy_from_slice(b"solid");
+        assert!(is_ascii_stl(&binary));
+        assert_eq!(
+            read_stl(&binary),
+            Err((None, StlErrorReason::TruncatedFile))
+        );
+    }
+}
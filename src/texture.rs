@@ -1,9 +1,14 @@
 use std::borrow::Cow;
 use std::fs::File;
+use std::io::Read;
 use std::ops::{Add, Div, Mul, Sub};
 use std::path::Path;
+use std::sync::OnceLock;
 
-use crate::color::Color;
+use serde::Deserialize;
+use tinyvec::ArrayVec;
+
+use crate::color::{Color, LinearColor};
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub struct Tex2 {
@@ -118,26 +123,238 @@ impl std::fmt::Display for TextureError<'_> {
     }
 }
 
+/// How texels are combined when sampling between them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// Use the closest texel
+    #[default]
+    Nearest,
+    /// Blend the four closest texels by distance
+    Bilinear,
+}
+
+/// How texture coordinates outside of [0, 1] are mapped back onto the texture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    /// Tile the texture
+    #[default]
+    Repeat,
+    /// Stretch the texels at the edges
+    ClampToEdge,
+    /// Tile the texture, flipping every other tile
+    MirroredRepeat,
+}
+
+impl WrapMode {
+    /// Maps a texel coordinate onto a row or column of a texture with the given size
+    fn wrap(&self, coordinate: i64, size: u32) -> usize {
+        let size = size as i64;
+
+        let wrapped = match self {
+            Self::Repeat => coordinate.rem_euclid(size),
+            Self::ClampToEdge => coordinate.clamp(0, size - 1),
+            Self::MirroredRepeat => {
+                let mirrored = coordinate.rem_euclid(size * 2);
+
+                if mirrored < size {
+                    mirrored
+                } else {
+                    size * 2 - 1 - mirrored
+                }
+            }
+        };
+
+        wrapped as usize
+    }
+}
+
+/// Controls how a texture is filtered and wrapped when sampled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap_u: WrapMode, wrap_v: WrapMode) -> Self {
+        Self {
+            filter,
+            wrap_u,
+            wrap_v,
+        }
+    }
+}
+
+/// A type a texture's texels can be stored and sampled as
+trait Texel: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+
+    /// Sum of several texels, each multiplied by its weight
+    fn weighted_sum(texels: impl Iterator<Item = (Self, f32)>) -> Self;
+}
+
+impl Texel for Color {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Color::lerp(self, other, t)
+    }
+
+    fn weighted_sum(texels: impl Iterator<Item = (Self, f32)>) -> Self {
+        let sum = texels.fold([0.0; 4], |sum, (texel, weight)| {
+            let channels = [texel.r, texel.g, texel.b, texel.a];
+            std::array::from_fn(|i| sum[i] + channels[i] as f32 * weight)
+        });
+        let [r, g, b, a] = sum.map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+
+        Color::rgba(r, g, b, a)
+    }
+}
+
+impl Texel for LinearColor {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        LinearColor::lerp(self, other, t)
+    }
+
+    fn weighted_sum(texels: impl Iterator<Item = (Self, f32)>) -> Self {
+        texels.fold(
+            LinearColor::new(0.0, 0.0, 0.0, 0.0),
+            |sum, (texel, weight)| sum + texel * weight,
+        )
+    }
+}
+
+/// Source texels along one axis averaged into a texel of the next smaller mip level, with
+/// their weights. Odd sizes can't be split into pairs, so each smaller texel covers three
+/// source texels, weighted by how much of each it overlaps.
+fn downsample_taps(index: u32, size: u32, smaller_size: u32) -> ArrayVec<[(u32, f32); 3]> {
+    let mut taps = ArrayVec::new();
+
+    if size == 1 {
+        taps.push((0, 1.0));
+    } else if size.is_multiple_of(2) {
+        taps.extend([(index * 2, 0.5), (index * 2 + 1, 0.5)]);
+    } else {
+        let (index_f32, smaller_size_f32, size_f32) =
+            (index as f32, smaller_size as f32, size as f32);
+
+        taps.extend([
+            (index * 2, (smaller_size_f32 - index_f32) / size_f32),
+            (index * 2 + 1, smaller_size_f32 / size_f32),
+            (index * 2 + 2, (index_f32 + 1.0) / size_f32),
+        ]);
+    }
+
+    taps
+}
+
+/// One level of a texture's mip chain
+#[derive(Clone)]
+struct MipLevel<T> {
+    width: u32,
+    height: u32,
+    pixels: Vec<T>,
+}
+
+impl<T: Texel> MipLevel<T> {
+    /// Halves the level's size, averaging each 2x2 block of texels, or each 3x3 block along
+    /// odd sizes
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+        for y in 0..height {
+            let rows = downsample_taps(y, self.height, height);
+
+            for x in 0..width {
+                let columns = downsample_taps(x, self.width, width);
+                let block = rows.iter().flat_map(|&(src_y, row_weight)| {
+                    columns.iter().map(move |&(src_x, column_weight)| {
+                        (
+                            self.pixels[(src_y * self.width + src_x) as usize],
+                            row_weight * column_weight,
+                        )
+                    })
+                });
+
+                pixels.push(T::weighted_sum(block));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Builds a mip chain from its full size level, halving down to 1x1
+    fn chain(full_size: Self) -> Vec<Self> {
+        let mut levels = vec![full_size];
+
+        while let Some(level) = levels
+            .last()
+            .filter(|level| level.width > 1 || level.height > 1)
+        {
+            levels.push(level.downsample());
+        }
+
+        levels
+    }
+}
+
+#[derive(Clone)]
 pub struct Texture {
     pub width: u32,
     pub height: u32,
-    pub pixels: Vec<Color>,
+    /// Mip chain, starting with the full size texture and halving down to 1x1
+    levels: Vec<MipLevel<Color>>,
+    /// The same mip chain decoded from sRGB into linear light. Its smaller levels are
+    /// averaged in linear space, so they keep the brightness of the full size texture. It's
+    /// only built the first time the texture is sampled in linear light, so textures such as
+    /// normal maps never pay for it.
+    linear_levels: OnceLock<Vec<MipLevel<LinearColor>>>,
+    pub sampler: Sampler,
 }
 
 impl Texture {
-    pub fn from_color(width: u32, height: u32, color: Color) -> Self {
+    /// Creates a texture from its sRGB pixels in rows, building its mip chain
+    pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+
+        let levels = MipLevel::chain(MipLevel {
+            width,
+            height,
+            pixels,
+        });
+
         Self {
             width,
             height,
-            pixels: vec![color; width as usize * height as usize],
+            levels,
+            linear_levels: OnceLock::new(),
+            sampler: Sampler::default(),
         }
     }
 
-    pub fn from_png(path: &Path) -> Result<Self, TextureError> {
+    pub fn from_color(width: u32, height: u32, color: Color) -> Self {
+        Self::new(width, height, vec![color; width as usize * height as usize])
+    }
+
+    pub fn from_png(path: &Path) -> Result<Self, TextureError<'_>> {
         let png_file =
-            File::open(path).or_else(|_| Err(TextureError::ReadError(path.to_string_lossy())))?;
+            File::open(path).map_err(|_| TextureError::ReadError(path.to_string_lossy()))?;
 
-        let decoder = png::Decoder::new(png_file);
+        Self::from_png_reader(png_file)
+    }
+
+    /// Decodes a PNG from memory or any other source, such as an image embedded in a model
+    pub fn from_png_reader(reader: impl Read) -> Result<Self, TextureError<'static>> {
+        let decoder = png::Decoder::new(reader);
         let mut reader = decoder.read_info().or(Err(TextureError::DecodeError))?;
 
         let mut byte_buffer: Vec<u8> = vec![0; reader.output_buffer_size()];
@@ -152,7 +369,7 @@ impl Texture {
         let pixels = match frame_metadata.color_type {
             png::ColorType::Rgba => byte_buffer
                 .chunks_exact(4)
-                .map(|colors| Color::new(colors[0], colors[1], colors[2]))
+                .map(|colors| Color::rgba(colors[0], colors[1], colors[2], colors[3]))
                 .collect(),
             png::ColorType::Rgb => byte_buffer
                 .chunks_exact(3)
@@ -163,18 +380,224 @@ impl Texture {
             }
         };
 
-        Ok(Self {
-            width: frame_metadata.width,
-            height: frame_metadata.height,
+        Ok(Self::new(
+            frame_metadata.width,
+            frame_metadata.height,
             pixels,
-        })
+        ))
+    }
+
+    /// Number of levels in the texture's mip chain, including the full size texture
+    pub fn mip_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the mip level to sample given how far the texture coordinates move between
+    /// neighbouring pixels along the screen's x and y axes. Level 0 is the full size texture.
+    pub fn level_of_detail(&self, uv_dx: Tex2, uv_dy: Tex2) -> f32 {
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        let texels_dx = (uv_dx.u * width).hypot(uv_dx.v * height);
+        let texels_dy = (uv_dy.u * width).hypot(uv_dy.v * height);
+
+        texels_dx.max(texels_dy).log2().max(0.0)
     }
 
+    /// Samples the full size texture at a texture coordinate using the texture's sampler
     pub fn sample(&self, pos: Tex2) -> Color {
-        let col = ((self.width - 1) as f32 * pos.u) as usize % self.width as usize;
-        let row = ((self.height - 1) as f32 * pos.v) as usize % self.height as usize;
-        let index = row * self.height as usize + col;
+        self.sample_level(&self.levels[0], pos)
+    }
+
+    /// Samples the texture at a fractional mip level, blending between the two closest levels
+    pub fn sample_trilinear(&self, pos: Tex2, level_of_detail: f32) -> Color {
+        self.sample_chain(&self.levels, pos, level_of_detail)
+    }
+
+    /// Mip chain in linear light, decoded from the full size texture the first time it's used
+    fn linear_levels(&self) -> &[MipLevel<LinearColor>] {
+        self.linear_levels.get_or_init(|| {
+            let full_size = &self.levels[0];
+
+            MipLevel::chain(MipLevel {
+                width: full_size.width,
+                height: full_size.height,
+                pixels: full_size
+                    .pixels
+                    .iter()
+                    .map(|&pixel| LinearColor::from_srgb(pixel))
+                    .collect(),
+            })
+        })
+    }
+
+    /// Samples the full size texture in linear light
+    pub fn sample_linear(&self, pos: Tex2) -> LinearColor {
+        self.sample_level(&self.linear_levels()[0], pos)
+    }
+
+    /// Samples the texture in linear light at a fractional mip level
+    pub fn sample_trilinear_linear(&self, pos: Tex2, level_of_detail: f32) -> LinearColor {
+        self.sample_chain(self.linear_levels(), pos, level_of_detail)
+    }
+
+    fn sample_chain<T: Texel>(&self, levels: &[MipLevel<T>], pos: Tex2, level_of_detail: f32) -> T {
+        let max_level = levels.len() - 1;
+        let level_of_detail = level_of_detail.clamp(0.0, max_level as f32);
+
+        let level = level_of_detail.floor() as usize;
+        let blend = level_of_detail - level as f32;
+
+        if level == max_level || blend == 0.0 {
+            return self.sample_level(&levels[level], pos);
+        }
+
+        self.sample_level(&levels[level], pos)
+            .lerp(self.sample_level(&levels[level + 1], pos), blend)
+    }
+
+    fn sample_level<T: Texel>(&self, mip: &MipLevel<T>, pos: Tex2) -> T {
+        // Texel centers sit at half texel offsets
+        let x = pos.u * mip.width as f32;
+        let y = pos.v * mip.height as f32;
+
+        match self.sampler.filter {
+            Filter::Nearest => self.texel(mip, x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                // Huge texture coordinates saturate, so the neighbouring texels can't overflow
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let (x1, y1) = (x0.saturating_add(1), y0.saturating_add(1));
+
+                let top = self.texel(mip, x0, y0).lerp(self.texel(mip, x1, y0), tx);
+                let bottom = self.texel(mip, x0, y1).lerp(self.texel(mip, x1, y1), tx);
+
+                top.lerp(bottom, ty)
+            }
+        }
+    }
+
+    /// Returns the texel at a column and row of a mip level, wrapped onto the level
+    fn texel<T: Texel>(&self, mip: &MipLevel<T>, x: i64, y: i64) -> T {
+        let col = self.sampler.wrap_u.wrap(x, mip.width);
+        let row = self.sampler.wrap_v.wrap(y, mip.height);
+
+        mip.pixels[row * mip.width as usize + col]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient_texture(width: u32, height: u32) -> Texture {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| Color::new(x as u8, y as u8, 0)))
+            .collect();
+
+        Texture::new(width, height, pixels)
+    }
+
+    #[test]
+    fn non_square_textures_are_addressed_by_width() {
+        let texture = gradient_texture(4, 2);
+
+        assert_eq!(texture.sample(Tex2::new(0.9, 0.9)), Color::new(3, 1, 0));
+        assert_eq!(texture.sample(Tex2::new(0.3, 0.1)), Color::new(1, 0, 0));
+    }
+
+    #[test]
+    fn wrap_modes_map_coordinates_outside_of_texture() {
+        let mut texture = gradient_texture(4, 4);
+        let pos = Tex2::new(1.1, -0.1);
+
+        texture.sampler = Sampler::new(Filter::Nearest, WrapMode::Repeat, WrapMode::Repeat);
+        assert_eq!(texture.sample(pos), Color::new(0, 3, 0));
+
+        texture.sampler = Sampler::new(
+            Filter::Nearest,
+            WrapMode::ClampToEdge,
+            WrapMode::ClampToEdge,
+        );
+        assert_eq!(texture.sample(pos), Color::new(3, 0, 0));
+
+        texture.sampler = Sampler::new(
+            Filter::Nearest,
+            WrapMode::MirroredRepeat,
+            WrapMode::MirroredRepeat,
+        );
+        assert_eq!(texture.sample(pos), Color::new(3, 0, 0));
+    }
+
+    #[test]
+    fn bilinear_filter_blends_between_texels() {
+        let mut texture = Texture::new(2, 1, vec![Color::new(0, 0, 0), Color::new(255, 255, 255)]);
+        texture.sampler = Sampler::new(
+            Filter::Bilinear,
+            WrapMode::ClampToEdge,
+            WrapMode::ClampToEdge,
+        );
+
+        assert_eq!(
+            texture.sample(Tex2::new(0.5, 0.5)),
+            Color::new(127, 127, 127)
+        );
+        assert_eq!(texture.sample(Tex2::new(0.0, 0.5)), Color::new(0, 0, 0));
+    }
+
+    #[test]
+    fn huge_texture_coordinates_can_be_sampled() {
+        let mut texture = gradient_texture(1024, 4);
+
+        for wrap in [
+            WrapMode::Repeat,
+            WrapMode::ClampToEdge,
+            WrapMode::MirroredRepeat,
+        ] {
+            for filter in [Filter::Nearest, Filter::Bilinear] {
+                texture.sampler = Sampler::new(filter, wrap, wrap);
+
+                texture.sample(Tex2::new(1e7, 1e7));
+                texture.sample(Tex2::new(-1e30, f32::MAX));
+            }
+        }
+    }
+
+    #[test]
+    fn mip_chain_halves_down_to_one_texel() {
+        let texture = gradient_texture(8, 2);
+
+        assert_eq!(texture.mip_levels(), 4);
+        assert_eq!(texture.levels[1].width, 4);
+        assert_eq!(texture.levels[1].height, 1);
+        assert_eq!(texture.levels[1].pixels[0], Color::new(1, 1, 0));
+        assert_eq!(texture.levels[3].width, 1);
+    }
+
+    #[test]
+    fn odd_sizes_average_every_texel() {
+        let texture = gradient_texture(3, 1);
+        assert_eq!(texture.levels[1].pixels, [Color::new(1, 0, 0)]);
+
+        // Each texel of the smaller level covers two and a half texels
+        let row = [0, 50, 100, 150, 200].map(|r| Color::new(r, 0, 0)).to_vec();
+        let texture = Texture::new(5, 1, row);
+        assert_eq!(
+            texture.levels[1].pixels,
+            [Color::new(40, 0, 0), Color::new(160, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn level_of_detail_follows_texel_footprint() {
+        let texture = gradient_texture(64, 64);
+
+        let one_texel = Tex2::new(1.0 / 64.0, 0.0);
+        let four_texels = Tex2::new(0.0, 4.0 / 64.0);
 
-        self.pixels[index]
+        assert_eq!(texture.level_of_detail(one_texel, one_texel), 0.0);
+        assert_eq!(texture.level_of_detail(one_texel, four_texels), 2.0);
     }
 }
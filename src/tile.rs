@@ -0,0 +1,269 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::color_buffer::ColorBuffer;
+use crate::depth_buffer::DepthBuffer;
+use crate::triangle::{bounding_box, Triangle};
+use crate::vector::Vec4;
+
+/// Width and height of the tiles the screen is split into when rendering on several threads
+pub const TILE_SIZE: u16 = 64;
+
+/// Rectangular region of the screen, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScreenRect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl ScreenRect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// A triangle that has been projected into screen space, ready to be split into tiles
+pub(crate) trait ScreenTriangle {
+    fn screen_points(&self) -> &[Vec4; 3];
+}
+
+impl ScreenTriangle for Triangle {
+    fn screen_points(&self) -> &[Vec4; 3] {
+        &self.points
+    }
+}
+
+/// Returns the number of threads available to render with
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// The screen split into a grid of tiles, with the triangles overlapping each tile
+struct TileGrid {
+    tiles: Vec<ScreenRect>,
+    columns: usize,
+    /// Indices of the triangles overlapping each tile, in drawing order
+    bins: Vec<Vec<usize>>,
+}
+
+impl TileGrid {
+    fn new<T: ScreenTriangle>(width: u16, height: u16, triangles: &[&T]) -> Self {
+        let columns = width.div_ceil(TILE_SIZE) as usize;
+        let rows = height.div_ceil(TILE_SIZE) as usize;
+
+        let mut tiles = Vec::with_capacity(columns * rows);
+
+        for row in 0..rows as u16 {
+            for column in 0..columns as u16 {
+                let (x, y) = (column * TILE_SIZE, row * TILE_SIZE);
+
+                tiles.push(ScreenRect::new(
+                    x,
+                    y,
+                    TILE_SIZE.min(width - x),
+                    TILE_SIZE.min(height - y),
+                ));
+            }
+        }
+
+        let mut grid = Self {
+            bins: vec![Vec::new(); tiles.len()],
+            tiles,
+            columns,
+        };
+
+        for (index, &triangle) in triangles.iter().enumerate() {
+            grid.bin_triangle(index, triangle, width, height);
+        }
+
+        grid
+    }
+
+    /// Adds a triangle to the bins of every tile its bounding box overlaps
+    fn bin_triangle<T: ScreenTriangle>(
+        &mut self,
+        index: usize,
+        triangle: &T,
+        width: u16,
+        height: u16,
+    ) {
+        let (min_x, min_y, max_x, max_y) = bounding_box(triangle.screen_points());
+
+        // Same bounds as used when rasterizing
+        let min_x = min_x.floor().max(0.0) as u16;
+        let min_y = min_y.floor().max(0.0) as u16;
+        let max_x = max_x.ceil().min((width - 1) as f32) as u16;
+        let max_y = max_y.ceil().min((height - 1) as f32) as u16;
+
+        if min_x > max_x || min_y > max_y {
+            return;
+        }
+
+        for row in (min_y / TILE_SIZE) as usize..=(max_y / TILE_SIZE) as usize {
+            for column in (min_x / TILE_SIZE) as usize..=(max_x / TILE_SIZE) as usize {
+                self.bins[row * self.columns + column].push(index);
+            }
+        }
+    }
+}
+
+/// Draws triangles by splitting the screen into tiles and drawing the tiles in parallel.
+/// Each tile draws its triangles in their original order into its own copy of the buffers,
+/// so the result is identical to drawing every triangle on a single thread.
+pub(crate) fn draw_tiled<T, F>(
+    color_buffer: &mut ColorBuffer,
+    depth_buffer: &mut DepthBuffer,
+    triangles: &[&T],
+    threads: usize,
+    draw_triangle: F,
+) where
+    T: ScreenTriangle + Sync,
+    F: Fn(&mut ColorBuffer, &mut DepthBuffer, &T) + Sync,
+{
+    let grid = TileGrid::new(color_buffer.width(), color_buffer.height(), triangles);
+    let next_tile = AtomicUsize::new(0);
+
+    let drawn_tiles: Vec<(ScreenRect, ColorBuffer, DepthBuffer)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut drawn_tiles = Vec::new();
+
+                    loop {
+                        let tile_index = next_tile.fetch_add(1, Ordering::Relaxed);
+
+                        let (Some(&rect), Some(bin)) =
+                            (grid.tiles.get(tile_index), grid.bins.get(tile_index))
+                        else {
+                            break;
+                        };
+
+                        if bin.is_empty() {
+                            continue;
+                        }
+
+                        let mut tile_colors = color_buffer.copy_tile(rect);
+                        let mut tile_depths = depth_buffer.copy_tile(rect);
+
+                        for &triangle_index in bin {
+                            draw_triangle(
+                                &mut tile_colors,
+                                &mut tile_depths,
+                                triangles[triangle_index],
+                            );
+                        }
+
+                        drawn_tiles.push((rect, tile_colors, tile_depths));
+                    }
+
+                    drawn_tiles
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("tile rendering thread panicked"))
+            .collect()
+    });
+
+    for (rect, tile_colors, tile_depths) in drawn_tiles {
+        color_buffer.write_tile(rect, &tile_colors);
+        depth_buffer.write_tile(rect, &tile_depths);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Attributes;
+    use crate::color::{Color, FloatColor};
+    use crate::drawing::PixelState;
+    use crate::material::BlendMode;
+    use crate::texture::Tex2;
+    use crate::triangle::VertexData;
+    use crate::vector::Vec4;
+
+    fn triangle(points: [(f32, f32, f32); 3], color: Color) -> Triangle {
+        Triangle::new(
+            points.map(|(x, y, w)| Vec4::new(x, y, 0.0, w)),
+            [Tex2::default(); 3],
+            [VertexData {
+                light: FloatColor::WHITE,
+                color: Attributes::splat(1.0),
+                ..Default::default()
+            }
+            .into(); 3],
+            color,
+        )
+    }
+
+    #[test]
+    fn tiled_drawing_matches_single_threaded() {
+        let triangles = [
+            triangle(
+                [(10.3, 5.7, 2.0), (190.2, 20.1, 3.0), (40.9, 140.6, 4.0)],
+                Color::new(255, 0, 0),
+            ),
+            triangle(
+                [(200.0, 0.0, 1.5), (63.5, 149.0, 1.0), (63.5, 64.5, 5.0)],
+                Color::new(0, 255, 0),
+            ),
+            triangle(
+                [(0.0, 0.0, 1.0), (130.0, 64.0, 1.0), (64.0, 130.0, 1.0)],
+                Color::rgba(0, 0, 255, 100),
+            ),
+        ];
+        let triangles: Vec<&Triangle> = triangles.iter().collect();
+
+        let pixel_state = PixelState {
+            pixel_lighting: None,
+            blend_mode: BlendMode::Opaque,
+            opacity: 1.0,
+            alpha_cutoff: 0.5,
+            simd: true,
+            linear: true,
+        };
+        let draw_triangle = |color_buffer: &mut ColorBuffer,
+                             depth_buffer: &mut DepthBuffer,
+                             triangle: &Triangle| {
+            color_buffer.draw_filled_triangle(triangle, triangle.color, depth_buffer, pixel_state)
+        };
+
+        let mut color_buffer = ColorBuffer::new(200, 150);
+        let mut depth_buffer = DepthBuffer::new(200, 150);
+        for triangle in triangles.iter() {
+            draw_triangle(&mut color_buffer, &mut depth_buffer, triangle);
+        }
+
+        let mut tiled_color_buffer = ColorBuffer::new(200, 150);
+        let mut tiled_depth_buffer = DepthBuffer::new(200, 150);
+        draw_tiled(
+            &mut tiled_color_buffer,
+            &mut tiled_depth_buffer,
+            &triangles,
+            3,
+            draw_triangle,
+        );
+
+        assert!(color_buffer.buffer().iter().any(|&pixel| pixel != 0));
+        assert_eq!(color_buffer.buffer(), tiled_color_buffer.buffer());
+        assert_eq!(depth_buffer.buffer(), tiled_depth_buffer.buffer());
+    }
+
+    #[test]
+    fn edge_tiles_are_clipped_to_screen() {
+        let grid = TileGrid::new::<Triangle>(100, 70, &[]);
+
+        assert_eq!(grid.tiles.len(), 4);
+        assert_eq!(grid.tiles[1], ScreenRect::new(64, 0, 36, 64));
+        assert_eq!(grid.tiles[3], ScreenRect::new(64, 64, 36, 6));
+    }
+}
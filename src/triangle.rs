@@ -1,26 +1,34 @@
-use crate::color::Color;
-use crate::fixed::FixedI32;
+use std::ops::{Add, Div, Mul};
+
+use crate::attributes::Attributes;
+use crate::color::{Color, FloatColor};
 use crate::texture::Tex2;
-use crate::vector::Vec4;
+use crate::vector::{Vec3, Vec4};
 
 pub struct Face {
-    pub a: u16,
-    pub b: u16,
-    pub c: u16,
-    pub a_uv: u16,
-    pub b_uv: u16,
-    pub c_uv: u16,
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    pub a_uv: u32,
+    pub b_uv: u32,
+    pub c_uv: u32,
+    /// Indices into the mesh's vertex normals, if the face has any
+    pub normals: Option<[u32; 3]>,
+    /// Indices into the mesh's vertex tangents, once they have been generated
+    pub tangents: Option<[u32; 3]>,
+    /// Index into the mesh's materials, if the face has one
+    pub material: Option<u32>,
     pub color: Color,
 }
 
 impl Face {
     pub const fn new(
-        a: u16,
-        b: u16,
-        c: u16,
-        a_uv: u16,
-        b_uv: u16,
-        c_uv: u16,
+        a: u32,
+        b: u32,
+        c: u32,
+        a_uv: u32,
+        b_uv: u32,
+        c_uv: u32,
         color: Color,
     ) -> Self {
         Self {
@@ -30,60 +38,207 @@ impl Face {
             a_uv,
             b_uv,
             c_uv,
+            normals: None,
+            tangents: None,
+            material: None,
             color,
         }
     }
+
+    pub const fn with_normals(mut self, a_normal: u32, b_normal: u32, c_normal: u32) -> Self {
+        self.normals = Some([a_normal, b_normal, c_normal]);
+        self
+    }
+
+    pub const fn with_material(mut self, material: u32) -> Self {
+        self.material = Some(material);
+        self
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct VertexPos {
-    pub x: FixedI32,
-    pub y: FixedI32,
-    pub z: f32,
-    pub w: f32,
+/// Number of attributes the built-in pipeline carries with each vertex, laid out by
+/// `VertexData`
+pub const VERTEX_ATTRIBUTES: usize = 19;
+
+/// Vertex data the built-in pipeline interpolates across each triangle, packed into the
+/// attributes of its vertices
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VertexData {
+    /// Light arriving at the vertex
+    pub light: FloatColor,
+    /// World space normal
+    pub normal: Vec3,
+    /// World space position
+    pub world_pos: Vec3,
+    /// World space tangent, or zero if the mesh has none
+    pub tangent: Vec3,
+    /// World space bitangent, or zero if the mesh has none
+    pub bitangent: Vec3,
+    /// Red, green, blue and alpha from 0 to 1, which multiplies the surface color
+    pub color: Attributes<4>,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Vertex {
-    pub pos: VertexPos,
-    pub uv: Tex2,
+impl From<VertexData> for Attributes<VERTEX_ATTRIBUTES> {
+    fn from(data: VertexData) -> Self {
+        let VertexData {
+            light,
+            normal,
+            world_pos,
+            tangent,
+            bitangent,
+            color,
+        } = data;
+
+        Self::new([
+            light.r,
+            light.g,
+            light.b,
+            normal.x,
+            normal.y,
+            normal.z,
+            world_pos.x,
+            world_pos.y,
+            world_pos.z,
+            tangent.x,
+            tangent.y,
+            tangent.z,
+            bitangent.x,
+            bitangent.y,
+            bitangent.z,
+            color[0],
+            color[1],
+            color[2],
+            color[3],
+        ])
+    }
+}
+
+impl From<Attributes<VERTEX_ATTRIBUTES>> for VertexData {
+    fn from(attributes: Attributes<VERTEX_ATTRIBUTES>) -> Self {
+        let vec3 = |start: usize| {
+            Vec3::new(
+                attributes[start],
+                attributes[start + 1],
+                attributes[start + 2],
+            )
+        };
+
+        Self {
+            light: FloatColor::new(attributes[0], attributes[1], attributes[2]),
+            normal: vec3(3),
+            world_pos: vec3(6),
+            tangent: vec3(9),
+            bitangent: vec3(12),
+            color: Attributes::new([
+                attributes[15],
+                attributes[16],
+                attributes[17],
+                attributes[18],
+            ]),
+        }
+    }
 }
 
+/// A triangle in screen space, carrying `N` attributes with each vertex that are
+/// interpolated perspective-correctly across its pixels
 #[derive(Clone, Debug)]
-pub struct Triangle {
+pub struct Triangle<const N: usize = VERTEX_ATTRIBUTES> {
     pub points: [Vec4; 3],
     pub tex_coords: [Tex2; 3],
+    pub attributes: [Attributes<N>; 3],
     pub color: Color,
+    /// Material of the face the triangle was made from
+    pub material: Option<u32>,
 }
 
-impl Triangle {
+impl<const N: usize> Triangle<N> {
     pub fn new(
-        a: Vec4,
-        b: Vec4,
-        c: Vec4,
-        a_uv: Tex2,
-        b_uv: Tex2,
-        c_uv: Tex2,
+        points: [Vec4; 3],
+        tex_coords: [Tex2; 3],
+        attributes: [Attributes<N>; 3],
         color: Color,
     ) -> Self {
         Self {
-            points: [a, b, c],
-            tex_coords: [a_uv, b_uv, c_uv],
+            points,
+            tex_coords,
+            attributes,
             color,
+            material: None,
         }
     }
 
+    /// Interpolated 1/w at a point, given its barycentric coordinates
+    pub fn interpolate_reciprocal_w(&self, alpha: f32, beta: f32, gamma: f32) -> f32 {
+        1.0 / self.points[0].w * alpha
+            + 1.0 / self.points[1].w * beta
+            + 1.0 / self.points[2].w * gamma
+    }
+
+    /// Perspective correct interpolation of per-vertex values at a point
+    pub fn interpolate<T>(
+        &self,
+        values: [T; 3],
+        alpha: f32,
+        beta: f32,
+        gamma: f32,
+        reciprocal_w: f32,
+    ) -> T
+    where
+        T: Add<Output = T> + Mul<f32, Output = T> + Div<f32, Output = T>,
+    {
+        let [a, b, c] = values;
+
+        (a * (alpha / self.points[0].w)
+            + b * (beta / self.points[1].w)
+            + c * (gamma / self.points[2].w))
+            / reciprocal_w
+    }
+
+    /// How the barycentric coordinates change when moving one pixel along the screen's x
+    /// axis and along its y axis
+    pub fn barycentric_gradients(&self) -> ([f32; 3], [f32; 3]) {
+        let [a, b, c] = self.points;
+        let area = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+
+        (
+            [(b.y - c.y) / area, (c.y - a.y) / area, (a.y - b.y) / area],
+            [(c.x - b.x) / area, (a.x - c.x) / area, (b.x - a.x) / area],
+        )
+    }
+
     pub fn bounding_box(&self) -> (f32, f32, f32, f32) {
-        let a = self.points[0];
-        let b = self.points[1];
-        let c = self.points[2];
+        bounding_box(&self.points)
+    }
+}
+
+/// Returns the screen space bounding box of a triangle's points as (min x, min y, max x, max y)
+pub(crate) fn bounding_box(points: &[Vec4; 3]) -> (f32, f32, f32, f32) {
+    let [a, b, c] = points;
+
+    let min_x = a.x.min(b.x.min(c.x));
+    let min_y = a.y.min(b.y.min(c.y));
+
+    let max_x = a.x.max(b.x.max(c.x));
+    let max_y = a.y.max(b.y.max(c.y));
+
+    (min_x.floor(), min_y.floor(), max_x.ceil(), max_y.ceil())
+}
 
-        let min_x = a.x.min(b.x.min(c.x));
-        let min_y = a.y.min(b.y.min(c.y));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let max_x = a.x.max(b.x.max(c.x));
-        let max_y = a.y.max(b.y.max(c.y));
+    #[test]
+    fn vertex_data_survives_packing_into_attributes() {
+        let data = VertexData {
+            light: FloatColor::new(0.1, 0.2, 0.3),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            world_pos: Vec3::new(4.0, 5.0, 6.0),
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, -1.0),
+            color: Attributes::new([0.25, 0.5, 0.75, 1.0]),
+        };
 
-        (min_x.floor(), min_y.floor(), max_x.ceil(), max_y.ceil())
+        assert_eq!(VertexData::from(Attributes::from(data)), data);
     }
 }